@@ -0,0 +1,162 @@
+//! Procedural macros for [`cffi`](https://docs.rs/cffi).
+//!
+//! This crate is an implementation detail, its macros should be used through
+//! their re-exports in `cffi`.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{quote, quote_spanned};
+use syn::{
+    meta::ParseNestedMeta, parse_macro_input, spanned::Spanned, Error, Fields, ItemStruct, Path,
+};
+
+/// Declare an opaque C type.
+///
+/// This turns a unit struct into a [`Private`]-based opaque type, and
+/// implements [`Alloc`] for it using the function given as `free`. When both
+/// `retain` and `release` are given, [`Retain`] is implemented as well.
+///
+/// All functions must be `extern "C"` and take a single `*mut` pointer to the
+/// declared type. Their return values are ignored.
+///
+/// ```
+/// #[cffi::opaque(free = foo_delete)]
+/// pub struct Foo;
+///
+/// # unsafe extern "C" fn foo_new() -> *mut Foo { std::ptr::NonNull::dangling().as_ptr() }
+/// # unsafe extern "C" fn foo_delete(_: *mut Foo) {}
+/// let foo = unsafe { cffi::Ptr::new(foo_new()) }.unwrap();
+/// # drop(foo);
+/// ```
+///
+/// Functions with a Rust ABI are rejected:
+///
+/// ```compile_fail
+/// #[cffi::opaque(free = foo_delete)]
+/// pub struct Foo;
+///
+/// fn foo_delete(_: *mut Foo) {}
+/// ```
+///
+/// [`Private`]: ../cffi/struct.Private.html
+/// [`Alloc`]: ../cffi/trait.Alloc.html
+/// [`Retain`]: ../cffi/trait.Retain.html
+#[proc_macro_attribute]
+pub fn opaque(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut options = Options::default();
+    let parser = syn::meta::parser(|meta| options.parse(meta));
+    parse_macro_input!(args with parser);
+    let item = parse_macro_input!(input as ItemStruct);
+
+    match expand_opaque(options, item) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    free: Option<Path>,
+    retain: Option<Path>,
+    release: Option<Path>,
+}
+
+impl Options {
+    fn parse(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        let slot = if meta.path.is_ident("free") {
+            &mut self.free
+        } else if meta.path.is_ident("retain") {
+            &mut self.retain
+        } else if meta.path.is_ident("release") {
+            &mut self.release
+        } else {
+            return Err(meta.error("expected `free`, `retain` or `release`"));
+        };
+
+        if slot.is_some() {
+            return Err(meta.error("duplicate option"));
+        }
+
+        *slot = Some(meta.value()?.parse()?);
+        Ok(())
+    }
+}
+
+fn expand_opaque(options: Options, item: ItemStruct) -> syn::Result<proc_macro2::TokenStream> {
+    if !matches!(item.fields, Fields::Unit) {
+        return Err(Error::new(
+            item.fields.span(),
+            "opaque types must be declared as unit structs",
+        ));
+    }
+
+    if !item.generics.params.is_empty() || item.generics.where_clause.is_some() {
+        return Err(Error::new(
+            item.generics.span(),
+            "opaque types can't be generic",
+        ));
+    }
+
+    let free = options
+        .free
+        .ok_or_else(|| Error::new(Span::call_site(), "missing `free = ...` option"))?;
+
+    let ItemStruct {
+        attrs, vis, ident, ..
+    } = &item;
+
+    let free = call_extern(ident, &free);
+    let retain = match (options.retain, options.release) {
+        (Some(retain), Some(release)) => {
+            let retain = call_extern(ident, &retain);
+            let release = call_extern(ident, &release);
+            quote! {
+                impl ::cffi::Retain for #ident {
+                    fn retain(this: *mut Self) {
+                        #retain
+                    }
+
+                    fn release(this: *mut Self) {
+                        #release
+                    }
+                }
+            }
+        }
+        (None, None) => quote!(),
+        (Some(path), None) | (None, Some(path)) => {
+            return Err(Error::new(
+                path.span(),
+                "`retain` and `release` must be given together",
+            ));
+        }
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis struct #ident(::cffi::Private);
+
+        impl ::cffi::Alloc for #ident {
+            fn free(this: *mut Self) {
+                #free
+            }
+        }
+
+        #retain
+    })
+}
+
+/// Generate a call to `path` with `this`, verifying that `path` is
+/// an `extern "C"` function.
+fn call_extern(ident: &syn::Ident, path: &Path) -> proc_macro2::TokenStream {
+    let function = quote_spanned!(path.span()=>
+        let function: unsafe extern "C" fn(*mut #ident) -> _ = #path;
+    );
+    quote! {
+        #function
+        unsafe {
+            function(this);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    fmt,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+    slice,
+};
+
+use crate::{Deleter, LibcFree, NullPointer};
+
+/// Owned C array.
+///
+/// This is an owned `(T*, size_t)` pair, such as the one returned by
+/// `foo_list(foo_t*, size_t *out_len)`, which dereferences to `[T]`. When
+/// dropped, it first drops all elements and then frees the array itself with a
+/// [`Deleter`], by default libc's `free`.
+///
+/// Elements can themselves be owned pointers, e.g. an array of `foo_t*` can be
+/// represented as `CArray<Ptr<Foo>>`, in which case each element is freed
+/// before the array.
+///
+/// Many functions return a null pointer along with a zero length to indicate
+/// an empty array. Such a pair is accepted, producing an empty `CArray` which
+/// doesn't call its deleter. Only a null pointer with a non-zero length is
+/// rejected.
+///
+/// ```
+/// use std::cell::Cell;
+/// use cffi::{Alloc, CArray, Ptr};
+///
+/// thread_local!(static FREED: Cell<usize> = Cell::new(0));
+///
+/// struct Foo(cffi::Private);
+///
+/// impl Alloc for Foo {
+///     fn free(_: *mut Self) {
+///         FREED.with(|freed| freed.set(freed.get() + 1));
+///     }
+/// }
+///
+/// let raw = unsafe { libc::malloc(2 * std::mem::size_of::<*mut Foo>()) } as *mut *mut Foo;
+/// unsafe {
+///     *raw = std::ptr::NonNull::dangling().as_ptr();
+///     *raw.add(1) = std::ptr::NonNull::dangling().as_ptr();
+/// }
+/// drop(unsafe { CArray::<Ptr<Foo>>::new(raw as *mut Ptr<Foo>, 2) }.unwrap());
+/// assert_eq!(FREED.with(Cell::get), 2);
+/// ```
+///
+/// ```
+/// use cffi::CArray;
+///
+/// let raw = unsafe { libc::calloc(3, std::mem::size_of::<u32>()) } as *mut u32;
+/// let mut array: CArray<u32> = unsafe { CArray::new(raw, 3) }.unwrap();
+/// array[1] = 5;
+/// assert_eq!(&*array, &[0, 5, 0]);
+/// assert_eq!(CArray::into_vec(array), vec![0, 5, 0]);
+///
+/// fn assert_thread_safe<T: Send + Sync>() {}
+/// assert_thread_safe::<CArray<u32>>();
+///
+/// let empty: CArray<u32> = unsafe { CArray::new(std::ptr::null_mut(), 0) }.unwrap();
+/// assert!(empty.is_empty());
+/// assert!(unsafe { CArray::<u32>::new(std::ptr::null_mut(), 1) }.is_none());
+/// ```
+pub struct CArray<T, D: Deleter<T> = LibcFree> {
+    /// `None` for an empty array represented by a null pointer.
+    raw: Option<NonNull<T>>,
+    len: usize,
+    deleter: D,
+}
+
+unsafe impl<T: Send, D: Deleter<T> + Send> Send for CArray<T, D> {}
+unsafe impl<T: Sync, D: Deleter<T> + Sync> Sync for CArray<T, D> {}
+
+impl<T, D: Deleter<T> + Default> CArray<T, D> {
+    /// Take ownership of an array, returning `None` if the pointer is null
+    /// and the length isn't zero.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to `len` initialized
+    /// elements of `T`, which can be freed with `D`, and must not be owned by
+    /// anything else.
+    pub unsafe fn new(raw: *mut T, len: usize) -> Option<CArray<T, D>> {
+        CArray::new_with(raw, len, D::default())
+    }
+
+    /// Take ownership of an array, failing with [`NullPointer`] if the pointer
+    /// is null and the length isn't zero.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`CArray::new`].
+    pub unsafe fn try_from_raw(raw: *mut T, len: usize) -> Result<CArray<T, D>, NullPointer> {
+        CArray::new(raw, len).ok_or(NullPointer)
+    }
+
+    /// Take ownership of an array.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid pointer to `len` initialized elements of `T`,
+    /// which can be freed with `D`, and must not be owned by anything else. It
+    /// may only be null if `len` is zero.
+    pub unsafe fn from_raw(raw: *mut T, len: usize) -> CArray<T, D> {
+        CArray::from_raw_with(raw, len, D::default())
+    }
+}
+
+impl<T, D: Deleter<T>> CArray<T, D> {
+    /// Take ownership of an array freed with `deleter`, returning `None` if the
+    /// pointer is null and the length isn't zero.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to `len` initialized
+    /// elements of `T`, which can be freed with `deleter`, and must not be
+    /// owned by anything else.
+    pub unsafe fn new_with(raw: *mut T, len: usize, deleter: D) -> Option<CArray<T, D>> {
+        if raw.is_null() && len != 0 {
+            return None;
+        }
+        Some(CArray {
+            raw: NonNull::new(raw),
+            len,
+            deleter,
+        })
+    }
+
+    /// Take ownership of an array freed with `deleter`.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid pointer to `len` initialized elements of `T`,
+    /// which can be freed with `deleter`, and must not be owned by anything
+    /// else. It may only be null if `len` is zero.
+    pub unsafe fn from_raw_with(raw: *mut T, len: usize, deleter: D) -> CArray<T, D> {
+        debug_assert!(
+            !raw.is_null() || len == 0,
+            "CArray::from_raw_with called with a null pointer"
+        );
+        CArray {
+            raw: NonNull::new(raw),
+            len,
+            deleter,
+        }
+    }
+
+    /// Release ownership of the array, returning its pointer and length.
+    ///
+    /// Neither the elements nor the array are freed.
+    pub fn into_raw(array: CArray<T, D>) -> (*mut T, usize) {
+        let array = ManuallyDrop::new(array);
+        drop(unsafe { ptr::read(&array.deleter) });
+        (CArray::raw(&array), array.len)
+    }
+
+    pub fn as_ptr(array: &CArray<T, D>) -> *const T {
+        CArray::raw(array)
+    }
+
+    pub fn as_raw(array: &mut CArray<T, D>) -> *mut T {
+        CArray::raw(array)
+    }
+
+    fn raw(array: &CArray<T, D>) -> *mut T {
+        array.raw.map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    /// Pointer to the elements, which is never null.
+    fn data(&self) -> *mut T {
+        self.raw.unwrap_or_else(NonNull::dangling).as_ptr()
+    }
+
+    /// Move all elements into a [`Vec`] and free the array.
+    pub fn into_vec(array: CArray<T, D>) -> Vec<T> {
+        let array = ManuallyDrop::new(array);
+        let vec = array
+            .iter()
+            .map(|item| unsafe { ptr::read(item) })
+            .collect();
+        let mut deleter = unsafe { ptr::read(&array.deleter) };
+        if let Some(raw) = array.raw {
+            deleter.delete(raw.as_ptr());
+        }
+        vec
+    }
+}
+
+impl<T, D: Deleter<T>> AsRef<[T]> for CArray<T, D> {
+    fn as_ref(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T, D: Deleter<T>> AsMut<[T]> for CArray<T, D> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+impl<T, D: Deleter<T>> Borrow<[T]> for CArray<T, D> {
+    fn borrow(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T, D: Deleter<T>> BorrowMut<[T]> for CArray<T, D> {
+    fn borrow_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+impl<T, D: Deleter<T>> Deref for CArray<T, D> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.data(), self.len) }
+    }
+}
+
+impl<T, D: Deleter<T>> DerefMut for CArray<T, D> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.data(), self.len) }
+    }
+}
+
+impl<T: fmt::Debug, D: Deleter<T>> fmt::Debug for CArray<T, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T, D: Deleter<T>> Drop for CArray<T, D> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(&mut **self as *mut [T]) };
+        if let Some(raw) = self.raw {
+            self.deleter.delete(raw.as_ptr());
+        }
+    }
+}
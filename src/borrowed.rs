@@ -0,0 +1,224 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+use crate::{Movable, NullPointer, ThreadSafe};
+
+/// Borrowed pointer.
+///
+/// This is a non-owning counterpart of [`Ptr`], meant for pointers returned by
+/// getters such as `foo_get_child(foo_t*)`, which remain owned by some parent
+/// object. It is never freed, and the lifetime `'a` ties it to the parent so
+/// that it can't outlive it.
+///
+/// ```
+/// use cffi::Ref;
+///
+/// struct Parent {
+///     child: u32,
+/// }
+///
+/// impl Parent {
+///     fn child(&self) -> Ref<u32> {
+///         let raw = &self.child as *const u32 as *mut u32;
+///         unsafe { Ref::new(self, raw) }.unwrap()
+///     }
+/// }
+///
+/// let parent = Parent { child: 7 };
+/// assert_eq!(*parent.child(), 7);
+/// ```
+///
+/// `Ref<T>` is [`Send`] and [`Sync`] if `T` is [`ThreadSafe`].
+///
+/// [`Ptr`]: crate::Ptr
+#[repr(transparent)]
+pub struct Ref<'a, T>(NonNull<T>, PhantomData<&'a T>);
+
+unsafe impl<T: ThreadSafe> Send for Ref<'_, T> {}
+unsafe impl<T: ThreadSafe> Sync for Ref<'_, T> {}
+
+/// Mutably borrowed pointer.
+///
+/// Same as [`Ref`], except that it holds a unique borrow of its parent, and
+/// thus allows mutation of the pointee. It is [`Send`] if `T` is [`Movable`],
+/// and [`Sync`] if `T` is [`ThreadSafe`].
+#[repr(transparent)]
+pub struct RefMut<'a, T>(NonNull<T>, PhantomData<&'a mut T>);
+
+unsafe impl<T: Movable> Send for RefMut<'_, T> {}
+unsafe impl<T: ThreadSafe> Sync for RefMut<'_, T> {}
+
+impl<'a, T> Ref<'a, T> {
+    /// Borrow a pointer owned by `parent`, returning `None` if it is null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to `T` which remains
+    /// valid, and is not mutated, for as long as `parent` is borrowed.
+    pub unsafe fn new<P: ?Sized>(_parent: &'a P, raw: *mut T) -> Option<Ref<'a, T>> {
+        NonNull::new(raw).map(|ptr| Ref(ptr, PhantomData))
+    }
+
+    /// Borrow a pointer owned by `parent`, failing with [`NullPointer`] if it
+    /// is null.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`Ref::new`].
+    pub unsafe fn try_from_raw<P: ?Sized>(
+        parent: &'a P,
+        raw: *mut T,
+    ) -> Result<Ref<'a, T>, NullPointer> {
+        Ref::new(parent, raw).ok_or(NullPointer)
+    }
+
+    /// Borrow a pointer for an arbitrary lifetime.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid, non-null pointer to `T` which remains valid, and
+    /// is not mutated, for the whole lifetime `'a`.
+    pub unsafe fn from_raw(raw: *mut T) -> Ref<'a, T> {
+        debug_assert!(!raw.is_null(), "Ref::from_raw called with a null pointer");
+        Ref(NonNull::new_unchecked(raw), PhantomData)
+    }
+
+    pub fn as_ptr(ptr: &Ref<'a, T>) -> *const T {
+        ptr.0.as_ptr()
+    }
+
+    /// Convert into a plain reference with the same lifetime.
+    pub fn into_ref(ptr: Ref<'a, T>) -> &'a T {
+        unsafe { &*ptr.0.as_ptr() }
+    }
+}
+
+impl<'a, T> RefMut<'a, T> {
+    /// Mutably borrow a pointer owned by `parent`, returning `None` if it is
+    /// null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to `T` which remains
+    /// valid, and is not accessed through any other pointer, for as long as
+    /// `parent` is borrowed.
+    pub unsafe fn new<P: ?Sized>(_parent: &'a mut P, raw: *mut T) -> Option<RefMut<'a, T>> {
+        NonNull::new(raw).map(|ptr| RefMut(ptr, PhantomData))
+    }
+
+    /// Mutably borrow a pointer owned by `parent`, failing with
+    /// [`NullPointer`] if it is null.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`RefMut::new`].
+    pub unsafe fn try_from_raw<P: ?Sized>(
+        parent: &'a mut P,
+        raw: *mut T,
+    ) -> Result<RefMut<'a, T>, NullPointer> {
+        RefMut::new(parent, raw).ok_or(NullPointer)
+    }
+
+    /// Mutably borrow a pointer for an arbitrary lifetime.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid, non-null pointer to `T` which remains valid, and
+    /// is not accessed through any other pointer, for the whole lifetime `'a`.
+    pub unsafe fn from_raw(raw: *mut T) -> RefMut<'a, T> {
+        debug_assert!(
+            !raw.is_null(),
+            "RefMut::from_raw called with a null pointer"
+        );
+        RefMut(NonNull::new_unchecked(raw), PhantomData)
+    }
+
+    pub fn as_ptr(ptr: &RefMut<'a, T>) -> *const T {
+        ptr.0.as_ptr()
+    }
+
+    pub fn as_raw(ptr: &mut RefMut<'a, T>) -> *mut T {
+        ptr.0.as_ptr()
+    }
+
+    /// Convert into a plain mutable reference with the same lifetime.
+    pub fn into_mut(ptr: RefMut<'a, T>) -> &'a mut T {
+        unsafe { &mut *ptr.0.as_ptr() }
+    }
+}
+
+impl<T> Clone for Ref<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Ref<'_, T> {}
+
+impl<'a, T> From<RefMut<'a, T>> for Ref<'a, T> {
+    fn from(ptr: RefMut<'a, T>) -> Ref<'a, T> {
+        Ref(ptr.0, PhantomData)
+    }
+}
+
+impl<T> AsRef<T> for Ref<'_, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> Borrow<T> for Ref<'_, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl<T> AsRef<T> for RefMut<'_, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> AsMut<T> for RefMut<'_, T> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T> Borrow<T> for RefMut<'_, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T> BorrowMut<T> for RefMut<'_, T> {
+    fn borrow_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T> Deref for RefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl<T> DerefMut for RefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.0.as_mut() }
+    }
+}
@@ -0,0 +1,186 @@
+//! Helpers for C functions which write into caller-provided buffers.
+//!
+//! Such functions (`snprintf`, `getcwd`, `readlink`, `getxattr`, ...) can't be
+//! told in advance how large a buffer is needed. Instead they either report
+//! the required size, or fail with something like `ERANGE` when the buffer is
+//! too small, and have to be called again with a larger one. [`negotiate`]
+//! implements this loop.
+//!
+//! ```
+//! use std::{io, os::raw::c_char};
+//! use cffi::buffer::{negotiate_c_string, Attempt, NegotiateError};
+//!
+//! let cwd = negotiate_c_string(4, 1 << 16, |buf| {
+//!     let ret = unsafe { libc::getcwd(buf.as_mut_ptr() as *mut c_char, buf.len()) };
+//!     if !ret.is_null() {
+//!         return Ok(Attempt::Done(buf.iter().position(|&b| b == 0).unwrap()));
+//!     }
+//!     match io::Error::last_os_error() {
+//!         error if error.raw_os_error() == Some(libc::ERANGE) => Ok(Attempt::TooSmall),
+//!         error => Err(error),
+//!     }
+//! })
+//! .unwrap();
+//! assert_eq!(cwd.to_str().unwrap(), std::env::current_dir().unwrap().to_str().unwrap());
+//! ```
+
+use std::{error::Error, ffi::CString, fmt, str::Utf8Error};
+
+/// Outcome of a single call in [`negotiate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attempt {
+    /// The call succeeded and wrote the given number of elements.
+    Done(usize),
+    /// The buffer was too small, and the call reported how many elements it
+    /// needs.
+    Needs(usize),
+    /// The buffer was too small, and the call didn't report how many elements
+    /// it needs. The buffer's size will be doubled.
+    TooSmall,
+}
+
+impl Attempt {
+    /// Interpret a call which returns the number of elements it needs (or
+    /// would have written), such as `snprintf`, given the buffer's length.
+    ///
+    /// ```
+    /// use std::os::raw::c_char;
+    /// use cffi::buffer::{negotiate_string, Attempt};
+    ///
+    /// let string = negotiate_string(0, 1024, |buf| {
+    ///     let fmt = b"%d-%s\0".as_ptr() as *const c_char;
+    ///     let arg = b"abc\0".as_ptr() as *const c_char;
+    ///     let len = unsafe { libc::snprintf(buf.as_mut_ptr() as *mut c_char, buf.len(), fmt, 42, arg) };
+    ///     // snprintf also needs space for the terminator.
+    ///     Ok::<_, ()>(Attempt::from_required(len as usize + 1, buf.len()))
+    /// });
+    /// assert_eq!(string.unwrap(), "42-abc");
+    /// ```
+    pub fn from_required(required: usize, len: usize) -> Attempt {
+        if required <= len {
+            Attempt::Done(required)
+        } else {
+            Attempt::Needs(required)
+        }
+    }
+}
+
+/// Error returned by [`negotiate`].
+#[derive(Debug)]
+pub enum NegotiateError<E> {
+    /// The call failed.
+    Call(E),
+    /// The call needs a buffer larger than the allowed maximum.
+    TooLarge,
+    /// The result was expected to be a string, but is not valid UTF-8.
+    InvalidUtf8(Utf8Error),
+}
+
+impl<E: fmt::Display> fmt::Display for NegotiateError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NegotiateError::Call(error) => error.fmt(f),
+            NegotiateError::TooLarge => f.write_str("required buffer is too large"),
+            NegotiateError::InvalidUtf8(error) => error.fmt(f),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for NegotiateError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NegotiateError::Call(error) => Some(error),
+            NegotiateError::TooLarge => None,
+            NegotiateError::InvalidUtf8(error) => Some(error),
+        }
+    }
+}
+
+/// Call `attempt` with increasingly large buffers until it succeeds.
+///
+/// The first call is made with a buffer of `initial` elements, which may be
+/// zero for functions which report the required size when given an empty
+/// buffer. Buffers never grow beyond `max` elements, and negotiation fails
+/// with [`NegotiateError::TooLarge`] as soon as a call reports needing more.
+/// The returned vector is truncated to the number of elements written.
+///
+/// ```
+/// use cffi::buffer::{negotiate, Attempt, NegotiateError};
+///
+/// let mut calls = 0;
+/// let result = negotiate::<u8, (), _>(0, 16, |_| {
+///     calls += 1;
+///     Ok(Attempt::Needs(17))
+/// });
+/// assert!(matches!(result, Err(NegotiateError::TooLarge)));
+/// assert_eq!(calls, 1);
+/// ```
+pub fn negotiate<T, E, F>(
+    initial: usize,
+    max: usize,
+    mut attempt: F,
+) -> Result<Vec<T>, NegotiateError<E>>
+where
+    T: Clone + Default,
+    F: FnMut(&mut [T]) -> Result<Attempt, E>,
+{
+    if initial > max {
+        return Err(NegotiateError::TooLarge);
+    }
+
+    let mut buffer = vec![T::default(); initial];
+    loop {
+        let len = match attempt(&mut buffer).map_err(NegotiateError::Call)? {
+            Attempt::Done(len) => {
+                assert!(len <= buffer.len(), "call wrote past the end of the buffer");
+                buffer.truncate(len);
+                return Ok(buffer);
+            }
+            // A buffer of `max` elements is known to be too small, so there's
+            // no point in making another call with it.
+            Attempt::Needs(len) if len > max => return Err(NegotiateError::TooLarge),
+            // Guard against reports which would not grow the buffer, to
+            // ensure the loop terminates.
+            Attempt::Needs(len) if len > buffer.len() => len,
+            Attempt::Needs(_) | Attempt::TooSmall => buffer.len().saturating_mul(2).max(1),
+        };
+
+        if buffer.len() == max {
+            return Err(NegotiateError::TooLarge);
+        }
+        buffer.resize(len.min(max), T::default());
+    }
+}
+
+/// Same as [`negotiate`], but producing a C string.
+///
+/// The result is truncated at its first nul byte, if any.
+pub fn negotiate_c_string<E, F>(
+    initial: usize,
+    max: usize,
+    attempt: F,
+) -> Result<CString, NegotiateError<E>>
+where
+    F: FnMut(&mut [u8]) -> Result<Attempt, E>,
+{
+    let mut bytes = negotiate(initial, max, attempt)?;
+    if let Some(nul) = bytes.iter().position(|&byte| byte == 0) {
+        bytes.truncate(nul);
+    }
+    Ok(CString::new(bytes).expect("nul bytes were removed"))
+}
+
+/// Same as [`negotiate`], but producing a UTF-8 string.
+///
+/// The result is truncated at its first nul byte, if any.
+pub fn negotiate_string<E, F>(
+    initial: usize,
+    max: usize,
+    attempt: F,
+) -> Result<String, NegotiateError<E>>
+where
+    F: FnMut(&mut [u8]) -> Result<Attempt, E>,
+{
+    let bytes = negotiate_c_string(initial, max, attempt)?.into_bytes();
+    String::from_utf8(bytes).map_err(|error| NegotiateError::InvalidUtf8(error.utf8_error()))
+}
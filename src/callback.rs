@@ -0,0 +1,373 @@
+use std::{
+    cell::{Cell, UnsafeCell},
+    os::raw::c_void,
+};
+
+use crate::{panic::catch_unwind_with, Abort, Sentinel};
+
+/// Closure which can be passed to C as a function pointer and a `void*` user
+/// data pointer.
+///
+/// C APIs usually accept callbacks as a pair of a function pointer, such as
+/// `void (*cb)(void *ud, int ev)`, and an opaque pointer which is passed back
+/// to it. [`Trampoline::trampoline`] returns a function matching the closure's
+/// signature (with an additional leading `*mut c_void`), which forwards calls
+/// to the closure identified by [`Callback::user_data`].
+///
+/// The closure is freed when the `Callback` is dropped, so it has to outlive
+/// its use by C.
+///
+/// Panics never unwind into C. Instead, the trampoline returns the callback's
+/// [`Sentinel`], and the panic is stored until it is re-raised with
+/// [`resume_pending_panic`]. Callbacks created with [`Callback::new`] use
+/// [`Abort`], which aborts the process instead of returning.
+///
+/// ```
+/// use std::{cell::Cell, os::raw::{c_int, c_void}};
+/// use cffi::{Callback, Trampoline};
+///
+/// # unsafe extern "C" fn emit(cb: unsafe extern "C" fn(*mut c_void, c_int), ud: *mut c_void) {
+/// #     cb(ud, 1);
+/// #     cb(ud, 2);
+/// # }
+/// let sum = Cell::new(0);
+/// let callback = Callback::new(|ev: c_int| sum.set(sum.get() + ev));
+/// unsafe { emit(callback.trampoline(), callback.user_data()) };
+/// assert_eq!(sum.get(), 3);
+/// ```
+///
+/// A panicking callback returns its sentinel, and the panic is re-raised once
+/// the C function returns:
+///
+/// ```
+/// use std::os::raw::{c_int, c_void};
+/// use cffi::{Callback, Trampoline};
+///
+/// # unsafe extern "C" fn sum(cb: unsafe extern "C" fn(*mut c_void, c_int) -> c_int, ud: *mut c_void) -> c_int {
+/// #     (0..3).map(|ev| cb(ud, ev)).sum()
+/// # }
+/// let callback = Callback::with_sentinel(
+///     |ev: c_int| if ev == 1 { panic!("bad event") } else { ev * 10 },
+///     -1,
+/// );
+/// assert_eq!(unsafe { sum(callback.trampoline(), callback.user_data()) }, 19);
+/// assert!(std::panic::catch_unwind(cffi::resume_pending_panic).is_err());
+/// ```
+///
+/// [`resume_pending_panic`]: crate::resume_pending_panic
+pub struct Callback<F, S = Abort>(Box<Inner<F, S>>);
+
+struct Inner<F, S> {
+    function: F,
+    sentinel: S,
+}
+
+/// Mutable closure which can be passed to C as a function pointer and a
+/// `void*` user data pointer.
+///
+/// This is the [`FnMut`] counterpart of [`Callback`]. Since the closure can't
+/// be called again while it is already running, a re-entrant call from C (for
+/// example when the closure calls back into the library, which in turn calls
+/// the callback) panics.
+///
+/// ```
+/// use std::os::raw::{c_int, c_void};
+/// use cffi::{CallbackMut, Trampoline};
+///
+/// # unsafe extern "C" fn emit(cb: unsafe extern "C" fn(*mut c_void, c_int), ud: *mut c_void) {
+/// #     cb(ud, 1);
+/// #     cb(ud, 2);
+/// # }
+/// let mut events = Vec::new();
+/// let callback = CallbackMut::new(|ev: c_int| events.push(ev));
+/// unsafe { emit(callback.trampoline(), callback.user_data()) };
+/// drop(callback);
+/// assert_eq!(events, [1, 2]);
+/// ```
+///
+/// A re-entrant call returns the callback's sentinel, leaving the panic
+/// pending:
+///
+/// ```
+/// use std::{cell::Cell, os::raw::{c_int, c_void}};
+/// use cffi::{CallbackMut, Trampoline};
+///
+/// type Function = unsafe extern "C" fn(*mut c_void, c_int) -> c_int;
+///
+/// let this: Cell<Option<(Function, *mut c_void)>> = Cell::new(None);
+/// let callback = CallbackMut::with_sentinel(
+///     |depth: c_int| match this.get() {
+///         Some((function, user_data)) if depth == 0 => unsafe { function(user_data, 1) },
+///         _ => depth,
+///     },
+///     -1,
+/// );
+/// this.set(Some((callback.trampoline(), callback.user_data())));
+///
+/// let (function, user_data) = this.get().unwrap();
+/// assert_eq!(unsafe { function(user_data, 0) }, -1);
+/// let payload = cffi::take_pending_panic().unwrap();
+/// assert_eq!(payload.downcast_ref::<&str>(), Some(&"re-entrant call to a CallbackMut"));
+///
+/// // The callback can still be called once the re-entrant call has returned.
+/// assert_eq!(unsafe { function(user_data, 5) }, 5);
+/// ```
+pub struct CallbackMut<F, S = Abort>(Box<MutInner<F, S>>);
+
+struct MutInner<F, S> {
+    running: Cell<bool>,
+    function: UnsafeCell<F>,
+    sentinel: S,
+}
+
+/// One-shot closure which can be passed to C as a function pointer and a
+/// `void*` user data pointer.
+///
+/// This is the [`FnOnce`] counterpart of [`Callback`]. The closure is consumed
+/// by the first call, and any further call panics.
+///
+/// ```
+/// use std::os::raw::{c_int, c_void};
+/// use cffi::{CallbackOnce, Trampoline};
+///
+/// # unsafe extern "C" fn complete(cb: unsafe extern "C" fn(*mut c_void, c_int) -> c_int, ud: *mut c_void) -> c_int {
+/// #     cb(ud, 20)
+/// # }
+/// let offset = String::from("22");
+/// let callback = CallbackOnce::new(move |value: c_int| value + offset.parse::<c_int>().unwrap());
+/// assert_eq!(unsafe { complete(callback.trampoline(), callback.user_data()) }, 42);
+/// assert!(callback.is_consumed());
+/// ```
+///
+/// Calling it again returns the callback's sentinel, leaving the panic
+/// pending:
+///
+/// ```
+/// use std::os::raw::c_int;
+/// use cffi::{CallbackOnce, Trampoline};
+///
+/// let callback = CallbackOnce::with_sentinel(|value: c_int| value * 2, -1);
+/// let function = callback.trampoline();
+/// assert_eq!(unsafe { function(callback.user_data(), 21) }, 42);
+/// assert!(cffi::take_pending_panic().is_none());
+///
+/// assert_eq!(unsafe { function(callback.user_data(), 21) }, -1);
+/// let payload = cffi::take_pending_panic().unwrap();
+/// assert_eq!(
+///     payload.downcast_ref::<String>().map(String::as_str),
+///     Some("CallbackOnce called more than once"),
+/// );
+/// ```
+pub struct CallbackOnce<F, S = Abort>(Box<OnceInner<F, S>>);
+
+struct OnceInner<F, S> {
+    function: Cell<Option<F>>,
+    sentinel: S,
+}
+
+/// Adapter between a callback and an `extern "C"` function.
+///
+/// This trait is implemented for [`Callback`], [`CallbackMut`] and
+/// [`CallbackOnce`] wrapping closures of up to six arguments, and `Args` is a
+/// tuple of the closure's argument types.
+pub trait Trampoline<Args> {
+    /// Type of the `extern "C"` function.
+    type Function;
+
+    /// Get a function which calls the closure whose
+    /// [user data](Callback::user_data) it is passed as its first argument.
+    fn trampoline(&self) -> Self::Function;
+}
+
+impl<F> Callback<F> {
+    pub fn new(function: F) -> Callback<F> {
+        Callback::with_sentinel(function, Abort)
+    }
+}
+
+impl<F, S> Callback<F, S> {
+    /// Create a callback which returns `sentinel` to C when it panics.
+    pub fn with_sentinel(function: F, sentinel: S) -> Callback<F, S> {
+        Callback(Box::new(Inner { function, sentinel }))
+    }
+
+    /// Pointer identifying this callback, to be passed to its
+    /// [trampoline](Trampoline::trampoline).
+    ///
+    /// It remains valid for as long as this callback is alive.
+    pub fn user_data(&self) -> *mut c_void {
+        &*self.0 as *const Inner<F, S> as *mut c_void
+    }
+}
+
+impl<F> CallbackMut<F> {
+    pub fn new(function: F) -> CallbackMut<F> {
+        CallbackMut::with_sentinel(function, Abort)
+    }
+}
+
+impl<F, S> CallbackMut<F, S> {
+    /// Create a callback which returns `sentinel` to C when it panics.
+    pub fn with_sentinel(function: F, sentinel: S) -> CallbackMut<F, S> {
+        CallbackMut(Box::new(MutInner {
+            running: Cell::new(false),
+            function: UnsafeCell::new(function),
+            sentinel,
+        }))
+    }
+
+    /// Pointer identifying this callback, to be passed to its
+    /// [trampoline](Trampoline::trampoline).
+    ///
+    /// It remains valid for as long as this callback is alive.
+    pub fn user_data(&self) -> *mut c_void {
+        &*self.0 as *const MutInner<F, S> as *mut c_void
+    }
+}
+
+impl<F, S> MutInner<F, S> {
+    /// Mark the closure as running, panicking if it already is.
+    ///
+    /// The closure is considered running until the returned guard is dropped.
+    fn enter(&self) -> RunningGuard<'_> {
+        if self.running.replace(true) {
+            panic!("re-entrant call to a CallbackMut");
+        }
+        RunningGuard(&self.running)
+    }
+}
+
+struct RunningGuard<'a>(&'a Cell<bool>);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+impl<F> CallbackOnce<F> {
+    pub fn new(function: F) -> CallbackOnce<F> {
+        CallbackOnce::with_sentinel(function, Abort)
+    }
+}
+
+impl<F, S> CallbackOnce<F, S> {
+    /// Create a callback which returns `sentinel` to C when it panics, or is
+    /// called more than once.
+    pub fn with_sentinel(function: F, sentinel: S) -> CallbackOnce<F, S> {
+        CallbackOnce(Box::new(OnceInner {
+            function: Cell::new(Some(function)),
+            sentinel,
+        }))
+    }
+
+    /// Pointer identifying this callback, to be passed to its
+    /// [trampoline](Trampoline::trampoline).
+    ///
+    /// It remains valid for as long as this callback is alive.
+    pub fn user_data(&self) -> *mut c_void {
+        &*self.0 as *const OnceInner<F, S> as *mut c_void
+    }
+
+    /// Returns `true` if the closure has already been called.
+    pub fn is_consumed(&self) -> bool {
+        let function = self.0.function.take();
+        let consumed = function.is_none();
+        self.0.function.set(function);
+        consumed
+    }
+}
+
+macro_rules! trampolines {
+    ($($arg:ident: $type:ident),*) => {
+        impl<F, S, R, $($type),*> Trampoline<($($type,)*)> for Callback<F, S>
+        where
+            F: Fn($($type),*) -> R,
+            S: Sentinel<R>,
+        {
+            type Function = unsafe extern "C" fn(*mut c_void, $($type),*) -> R;
+
+            fn trampoline(&self) -> Self::Function {
+                unsafe extern "C" fn trampoline<F, S, R, $($type),*>(
+                    user_data: *mut c_void,
+                    $($arg: $type),*
+                ) -> R
+                where
+                    F: Fn($($type),*) -> R,
+                    S: Sentinel<R>,
+                {
+                    let inner = &*(user_data as *const Inner<F, S>);
+                    catch_unwind_with(&inner.sentinel, || (inner.function)($($arg),*))
+                }
+
+                trampoline::<F, S, R, $($type),*>
+            }
+        }
+
+        impl<F, S, R, $($type),*> Trampoline<($($type,)*)> for CallbackMut<F, S>
+        where
+            F: FnMut($($type),*) -> R,
+            S: Sentinel<R>,
+        {
+            type Function = unsafe extern "C" fn(*mut c_void, $($type),*) -> R;
+
+            fn trampoline(&self) -> Self::Function {
+                unsafe extern "C" fn trampoline<F, S, R, $($type),*>(
+                    user_data: *mut c_void,
+                    $($arg: $type),*
+                ) -> R
+                where
+                    F: FnMut($($type),*) -> R,
+                    S: Sentinel<R>,
+                {
+                    let inner = &*(user_data as *const MutInner<F, S>);
+                    catch_unwind_with(&inner.sentinel, || {
+                        let _guard = inner.enter();
+                        let function = &mut *inner.function.get();
+                        function($($arg),*)
+                    })
+                }
+
+                trampoline::<F, S, R, $($type),*>
+            }
+        }
+
+        impl<F, S, R, $($type),*> Trampoline<($($type,)*)> for CallbackOnce<F, S>
+        where
+            F: FnOnce($($type),*) -> R,
+            S: Sentinel<R>,
+        {
+            type Function = unsafe extern "C" fn(*mut c_void, $($type),*) -> R;
+
+            fn trampoline(&self) -> Self::Function {
+                unsafe extern "C" fn trampoline<F, S, R, $($type),*>(
+                    user_data: *mut c_void,
+                    $($arg: $type),*
+                ) -> R
+                where
+                    F: FnOnce($($type),*) -> R,
+                    S: Sentinel<R>,
+                {
+                    let inner = &*(user_data as *const OnceInner<F, S>);
+                    catch_unwind_with(&inner.sentinel, || {
+                        let function = inner
+                            .function
+                            .take()
+                            .expect("CallbackOnce called more than once");
+                        function($($arg),*)
+                    })
+                }
+
+                trampoline::<F, S, R, $($type),*>
+            }
+        }
+    };
+}
+
+trampolines!();
+trampolines!(a: A);
+trampolines!(a: A, b: B);
+trampolines!(a: A, b: B, c: C);
+trampolines!(a: A, b: B, c: C, d: D);
+trampolines!(a: A, b: B, c: C, d: D, e: E);
+trampolines!(a: A, b: B, c: C, d: D, e: E, f: G);
@@ -0,0 +1,287 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{Alloc, NullPointer, Ptr};
+
+/// Owned pointer which must be freed before its parent.
+///
+/// Many C APIs hand out objects which are owned by the caller, but depend on
+/// some parent object, and must be freed before it (for example SQLite's
+/// `sqlite3_stmt` must be finalized before its `sqlite3` connection is
+/// closed). `ChildPtr` frees its pointee just like [`Ptr`], but also holds a
+/// borrow of the parent, so that the borrow checker forbids dropping the
+/// parent first.
+///
+/// ```
+/// use cffi::{Alloc, ChildPtr, Ptr};
+///
+/// struct Db(u32);
+/// struct Stmt(u32);
+///
+/// impl Alloc for Db {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// impl Alloc for Stmt {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// let db = unsafe { Ptr::from_raw(Box::into_raw(Box::new(Db(1)))) };
+/// let stmt = unsafe { ChildPtr::from_raw(&db, Box::into_raw(Box::new(Stmt(2)))) };
+/// assert_eq!(stmt.0, 2);
+/// drop(stmt);
+/// drop(db);
+/// ```
+///
+/// Dropping the parent while the child is alive is rejected:
+///
+/// ```compile_fail
+/// # use cffi::{Alloc, ChildPtr, Ptr};
+/// # struct Db;
+/// # struct Stmt;
+/// # impl Alloc for Db { fn free(_: *mut Self) {} }
+/// # impl Alloc for Stmt { fn free(_: *mut Self) {} }
+/// # let db = unsafe { Ptr::from_raw(std::ptr::NonNull::<Db>::dangling().as_ptr()) };
+/// # let raw = std::ptr::NonNull::<Stmt>::dangling().as_ptr();
+/// let stmt = unsafe { ChildPtr::from_raw(&db, raw) };
+/// drop(db);
+/// drop(stmt);
+/// ```
+///
+/// See [`SharedChildPtr`] for a version checked at runtime, for cases when
+/// the lifetime is impractical.
+#[repr(transparent)]
+pub struct ChildPtr<'p, T: Alloc>(Ptr<T>, PhantomData<&'p ()>);
+
+/// Owned pointer which keeps its parent alive.
+///
+/// Same as [`ChildPtr`], except that instead of borrowing its parent, it owns
+/// a handle `P` to it, such as an [`Rc`]`<`[`Ptr`]`<Parent>>` or a
+/// [`Shared`]`<Parent>`. The pointee is always freed before that handle is
+/// dropped, so as long as `P` keeps the parent alive, the parent will outlive
+/// its children.
+///
+/// ```
+/// use std::rc::Rc;
+/// use cffi::{Alloc, Ptr, SharedChildPtr};
+///
+/// struct Repo;
+/// struct Object;
+///
+/// impl Alloc for Repo {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// impl Alloc for Object {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// let repo = Rc::new(unsafe { Ptr::from_raw(Box::into_raw(Box::new(Repo))) });
+/// let object = unsafe {
+///     SharedChildPtr::from_raw(repo.clone(), Box::into_raw(Box::new(Object)))
+/// };
+/// drop(repo);
+/// assert_eq!(Rc::strong_count(SharedChildPtr::parent(&object)), 1);
+/// ```
+///
+/// [`Rc`]: std::rc::Rc
+/// [`Shared`]: crate::Shared
+pub struct SharedChildPtr<T: Alloc, P> {
+    // Fields are dropped in declaration order, freeing the child first.
+    ptr: Ptr<T>,
+    parent: P,
+}
+
+impl<'p, T: Alloc> ChildPtr<'p, T> {
+    /// Take ownership of a raw pointer depending on `parent`, returning
+    /// `None` if it is null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to `T` which can be freed
+    /// with `T`'s [`Alloc`] implementation, and must not be owned by anything
+    /// else. It must remain valid for as long as `parent` is borrowed.
+    pub unsafe fn new<P: ?Sized>(_parent: &'p P, raw: *mut T) -> Option<ChildPtr<'p, T>> {
+        Ptr::new(raw).map(|ptr| ChildPtr(ptr, PhantomData))
+    }
+
+    /// Take ownership of a raw pointer depending on `parent`, failing with
+    /// [`NullPointer`] if it is null.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`ChildPtr::new`].
+    pub unsafe fn try_from_raw<P: ?Sized>(
+        parent: &'p P,
+        raw: *mut T,
+    ) -> Result<ChildPtr<'p, T>, NullPointer> {
+        ChildPtr::new(parent, raw).ok_or(NullPointer)
+    }
+
+    /// Take ownership of a raw pointer depending on `parent`.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be non-null, and otherwise satisfy the same requirements as
+    /// for [`ChildPtr::new`].
+    pub unsafe fn from_raw<P: ?Sized>(_parent: &'p P, raw: *mut T) -> ChildPtr<'p, T> {
+        ChildPtr(Ptr::from_raw(raw), PhantomData)
+    }
+
+    pub fn into_raw(ptr: ChildPtr<'p, T>) -> *mut T {
+        Ptr::into_raw(ptr.0)
+    }
+
+    pub fn as_ptr(ptr: &ChildPtr<'p, T>) -> *const T {
+        Ptr::as_ptr(&ptr.0)
+    }
+
+    pub fn as_raw(ptr: &mut ChildPtr<'p, T>) -> *mut T {
+        Ptr::as_raw(&mut ptr.0)
+    }
+}
+
+impl<T: Alloc, P> SharedChildPtr<T, P> {
+    /// Take ownership of a raw pointer depending on `parent`, returning
+    /// `None` if it is null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to `T` which can be freed
+    /// with `T`'s [`Alloc`] implementation, and must not be owned by anything
+    /// else. It must remain valid for as long as `parent` is alive.
+    pub unsafe fn new(parent: P, raw: *mut T) -> Option<SharedChildPtr<T, P>> {
+        Ptr::new(raw).map(|ptr| SharedChildPtr { ptr, parent })
+    }
+
+    /// Take ownership of a raw pointer depending on `parent`, failing with
+    /// [`NullPointer`] if it is null.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`SharedChildPtr::new`].
+    pub unsafe fn try_from_raw(
+        parent: P,
+        raw: *mut T,
+    ) -> Result<SharedChildPtr<T, P>, NullPointer> {
+        SharedChildPtr::new(parent, raw).ok_or(NullPointer)
+    }
+
+    /// Take ownership of a raw pointer depending on `parent`.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be non-null, and otherwise satisfy the same requirements as
+    /// for [`SharedChildPtr::new`].
+    pub unsafe fn from_raw(parent: P, raw: *mut T) -> SharedChildPtr<T, P> {
+        SharedChildPtr {
+            ptr: Ptr::from_raw(raw),
+            parent,
+        }
+    }
+
+    /// Release ownership of the pointer, returning it along with the handle to
+    /// its parent.
+    pub fn into_raw_parts(ptr: SharedChildPtr<T, P>) -> (*mut T, P) {
+        (Ptr::into_raw(ptr.ptr), ptr.parent)
+    }
+
+    pub fn as_ptr(ptr: &SharedChildPtr<T, P>) -> *const T {
+        Ptr::as_ptr(&ptr.ptr)
+    }
+
+    pub fn as_raw(ptr: &mut SharedChildPtr<T, P>) -> *mut T {
+        Ptr::as_raw(&mut ptr.ptr)
+    }
+
+    pub fn parent(ptr: &SharedChildPtr<T, P>) -> &P {
+        &ptr.parent
+    }
+}
+
+impl<T: Alloc> AsRef<T> for ChildPtr<'_, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Alloc> AsMut<T> for ChildPtr<'_, T> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: Alloc> Borrow<T> for ChildPtr<'_, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Alloc> BorrowMut<T> for ChildPtr<'_, T> {
+    fn borrow_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: Alloc> Deref for ChildPtr<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Alloc> DerefMut for ChildPtr<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Alloc, P> AsRef<T> for SharedChildPtr<T, P> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Alloc, P> AsMut<T> for SharedChildPtr<T, P> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: Alloc, P> Borrow<T> for SharedChildPtr<T, P> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Alloc, P> BorrowMut<T> for SharedChildPtr<T, P> {
+    fn borrow_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: Alloc, P> Deref for SharedChildPtr<T, P> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.ptr
+    }
+}
+
+impl<T: Alloc, P> DerefMut for SharedChildPtr<T, P> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.ptr
+    }
+}
@@ -0,0 +1,185 @@
+use std::{fmt, process};
+
+use crate::{Alloc, Deleter, Ptr, PtrWith};
+
+/// Trait for FFI types whose destruction can fail or requires context.
+///
+/// This extends the [`Alloc`] model to destructors such as
+/// `int foo_close(foo_t*)`, which report errors, or
+/// `void foo_destroy(lib_t*, foo_t*)`, which need an additional argument. Types
+/// which need no context should use `()`.
+///
+/// Such objects can be owned through a [`PtrWith`] using a [`Closer`] as its
+/// deleter. They can be closed explicitly with [`PtrWith::close`], which
+/// returns the error. When the pointer is dropped instead, errors are handled
+/// according to the closer's [`DropPolicy`].
+///
+/// ```
+/// use std::sync::atomic::{AtomicI32, Ordering};
+/// use cffi::{Closer, DropPolicy, PtrWith, TryFree};
+///
+/// struct Foo(i32);
+///
+/// impl TryFree for Foo {
+///     type Context = ();
+///     type Error = i32;
+///
+///     fn try_free(this: *mut Self, _: &mut ()) -> Result<(), i32> {
+///         let foo = unsafe { Box::from_raw(this) };
+///         if foo.0 == 0 { Ok(()) } else { Err(foo.0) }
+///     }
+/// }
+///
+/// let raw = Box::into_raw(Box::new(Foo(3)));
+/// let ptr = unsafe { PtrWith::from_raw(raw, Closer::new(())) };
+/// assert_eq!(PtrWith::close(ptr), Err(3));
+///
+/// static DROP_ERROR: AtomicI32 = AtomicI32::new(0);
+/// let policy = DropPolicy::Hook(|error| DROP_ERROR.store(error, Ordering::SeqCst));
+///
+/// let raw = Box::into_raw(Box::new(Foo(0)));
+/// drop(unsafe { PtrWith::from_raw(raw, Closer::with_policy((), policy)) });
+/// assert_eq!(DROP_ERROR.load(Ordering::SeqCst), 0);
+///
+/// let raw = Box::into_raw(Box::new(Foo(5)));
+/// drop(unsafe { PtrWith::from_raw(raw, Closer::with_policy((), policy)) });
+/// assert_eq!(DROP_ERROR.load(Ordering::SeqCst), 5);
+/// ```
+///
+/// Types which need no context can also implement [`Alloc`], and be owned by
+/// a plain [`Ptr`], as returned by most of this crate. Such pointers can be
+/// closed explicitly with [`Ptr::close`], but when dropped they are freed
+/// with [`Alloc::free`], which has no way of reporting an error. It usually
+/// calls [`TryFree::try_free`] and handles the error itself:
+///
+/// ```
+/// use cffi::{Alloc, Ptr, TryFree};
+///
+/// struct Foo(i32);
+///
+/// impl TryFree for Foo {
+///     type Context = ();
+///     type Error = i32;
+///
+///     fn try_free(this: *mut Self, _: &mut ()) -> Result<(), i32> {
+///         let foo = unsafe { Box::from_raw(this) };
+///         if foo.0 == 0 { Ok(()) } else { Err(foo.0) }
+///     }
+/// }
+///
+/// impl Alloc for Foo {
+///     fn free(this: *mut Self) {
+///         if let Err(error) = Foo::try_free(this, &mut ()) {
+///             eprintln!("failed to close Foo: {}", error);
+///         }
+///     }
+/// }
+///
+/// let ptr = unsafe { Ptr::from_raw(Box::into_raw(Box::new(Foo(3)))) };
+/// assert_eq!(Ptr::close(ptr), Err(3));
+///
+/// let ptr = unsafe { Ptr::from_raw(Box::into_raw(Box::new(Foo(0)))) };
+/// assert_eq!(Ptr::close(ptr), Ok(()));
+/// ```
+pub trait TryFree {
+    type Context;
+    type Error;
+
+    fn try_free(this: *mut Self, context: &mut Self::Context) -> Result<(), Self::Error>;
+}
+
+/// What to do with an error from [`TryFree::try_free`] when a pointer is
+/// dropped rather than closed explicitly.
+#[derive(Default)]
+pub enum DropPolicy<E> {
+    /// Silently discard the error.
+    #[default]
+    Ignore,
+    /// Pass the error to a function, e.g. to log it.
+    Hook(fn(E)),
+    /// Abort the process.
+    Abort,
+}
+
+impl<E> DropPolicy<E> {
+    pub fn handle(&self, error: E) {
+        match *self {
+            DropPolicy::Ignore => {}
+            DropPolicy::Hook(hook) => hook(error),
+            DropPolicy::Abort => process::abort(),
+        }
+    }
+}
+
+impl<E> Clone for DropPolicy<E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for DropPolicy<E> {}
+
+impl<E> fmt::Debug for DropPolicy<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DropPolicy::Ignore => f.write_str("Ignore"),
+            DropPolicy::Hook(hook) => f.debug_tuple("Hook").field(&(hook as *const ())).finish(),
+            DropPolicy::Abort => f.write_str("Abort"),
+        }
+    }
+}
+
+/// Deleter for [`TryFree`] types.
+///
+/// It stores the context passed to [`TryFree::try_free`] and the
+/// [`DropPolicy`] applied when freeing fails during drop.
+pub struct Closer<T: TryFree> {
+    context: T::Context,
+    policy: DropPolicy<T::Error>,
+}
+
+impl<T: TryFree> Closer<T> {
+    /// Create a closer which ignores errors on drop.
+    pub fn new(context: T::Context) -> Closer<T> {
+        Closer::with_policy(context, DropPolicy::Ignore)
+    }
+
+    pub fn with_policy(context: T::Context, policy: DropPolicy<T::Error>) -> Closer<T> {
+        Closer { context, policy }
+    }
+
+    pub fn context(&self) -> &T::Context {
+        &self.context
+    }
+
+    pub fn policy(&self) -> DropPolicy<T::Error> {
+        self.policy
+    }
+}
+
+impl<T: TryFree> Deleter<T> for Closer<T> {
+    fn delete(&mut self, this: *mut T) {
+        if let Err(error) = T::try_free(this, &mut self.context) {
+            self.policy.handle(error);
+        }
+    }
+}
+
+impl<T: TryFree> PtrWith<T, Closer<T>> {
+    /// Free the pointee, returning any error reported by its destructor.
+    pub fn close(ptr: PtrWith<T, Closer<T>>) -> Result<(), T::Error> {
+        let (raw, mut closer) = PtrWith::into_raw_parts(ptr);
+        T::try_free(raw, &mut closer.context)
+    }
+}
+
+impl<T: Alloc + TryFree<Context = ()>> Ptr<T> {
+    /// Free the pointee through [`TryFree`] instead of [`Alloc`], returning
+    /// any error reported by its destructor.
+    ///
+    /// A `Ptr` which is dropped instead of closed is freed with
+    /// [`Alloc::free`], so any error is handled however that reports it.
+    pub fn close(ptr: Ptr<T>) -> Result<(), T::Error> {
+        T::try_free(Ptr::into_raw(ptr), &mut ())
+    }
+}
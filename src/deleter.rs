@@ -0,0 +1,259 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    mem,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+use crate::{Alloc, Movable, NullPointer, Ptr, ThreadSafe};
+
+/// Strategy for freeing a `T`.
+///
+/// Unlike [`Alloc`], which ties a single destroy function to a type, a deleter
+/// is a separate value, which lets the same type be freed in different ways
+/// depending on how it was obtained. Deleters are implemented for closures and
+/// function pointers taking `*mut T`, including `unsafe extern "C"` ones, and
+/// [`AllocDeleter`] adapts any [`Alloc`] type.
+///
+/// Functions declared in `extern "C"` blocks have their own, unique types,
+/// and have to be cast to a function pointer (`foo_close as unsafe extern "C"
+/// fn(*mut Foo)`) to be used as deleters. [`PtrWith::with_fn`] does this
+/// automatically.
+pub trait Deleter<T> {
+    fn delete(&mut self, this: *mut T);
+}
+
+/// Deleter which frees through the pointee's [`Alloc`] implementation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocDeleter;
+
+impl<T: Alloc> Deleter<T> for AllocDeleter {
+    fn delete(&mut self, this: *mut T) {
+        Alloc::free(this)
+    }
+}
+
+/// Deleter which frees through libc's `free`.
+///
+/// This is the right deleter for memory allocated with `malloc` and friends,
+/// as long as the library and the program share the same C runtime.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LibcFree;
+
+impl<T> Deleter<T> for LibcFree {
+    fn delete(&mut self, this: *mut T) {
+        unsafe { libc::free(this as *mut libc::c_void) }
+    }
+}
+
+impl<T, F: FnMut(*mut T)> Deleter<T> for F {
+    fn delete(&mut self, this: *mut T) {
+        self(this)
+    }
+}
+
+impl<T> Deleter<T> for unsafe fn(*mut T) {
+    fn delete(&mut self, this: *mut T) {
+        unsafe { self(this) }
+    }
+}
+
+impl<T> Deleter<T> for unsafe extern "C" fn(*mut T) {
+    fn delete(&mut self, this: *mut T) {
+        unsafe { self(this) }
+    }
+}
+
+/// Owned pointer with a custom deleter.
+///
+/// This is the same as [`Ptr`], except that the pointee is freed by a
+/// [`Deleter`] stored alongside the pointer instead of by its [`Alloc`]
+/// implementation. Zero-sized deleters add no overhead.
+///
+/// ```
+/// use std::cell::Cell;
+/// use cffi::PtrWith;
+///
+/// let freed = Cell::new(false);
+/// let mut value = 5;
+/// let ptr = unsafe { PtrWith::new(&mut value as *mut i32, |_| freed.set(true)) }.unwrap();
+/// assert_eq!(*ptr, 5);
+/// drop(ptr);
+/// assert!(freed.get());
+/// ```
+pub struct PtrWith<T, D: Deleter<T> = AllocDeleter> {
+    raw: NonNull<T>,
+    deleter: D,
+}
+
+unsafe impl<T: Movable, D: Deleter<T> + Send> Send for PtrWith<T, D> {}
+unsafe impl<T: ThreadSafe, D: Deleter<T> + Sync> Sync for PtrWith<T, D> {}
+
+impl<T> PtrWith<T, unsafe extern "C" fn(*mut T)> {
+    /// Take ownership of a raw pointer to be freed with a C function,
+    /// returning `None` if it is null.
+    ///
+    /// This is the same as [`PtrWith::new`], but accepts functions declared
+    /// in `extern "C"` blocks directly, which lets objects be freed with one
+    /// of several destructors depending on how they were obtained:
+    ///
+    /// ```
+    /// use std::{cell::Cell, os::raw::c_int};
+    /// use cffi::PtrWith;
+    ///
+    /// struct Foo(Cell<c_int>);
+    ///
+    /// thread_local!(static CLOSED: Cell<c_int> = Cell::new(0));
+    ///
+    /// unsafe extern "C" fn foo_close(foo: *mut Foo) {
+    ///     let foo = Box::from_raw(foo);
+    ///     CLOSED.with(|closed| closed.set(foo.0.get()));
+    /// }
+    ///
+    /// unsafe extern "C" fn foo_abort(foo: *mut Foo) {
+    ///     drop(Box::from_raw(foo));
+    ///     CLOSED.with(|closed| closed.set(-1));
+    /// }
+    ///
+    /// let raw = Box::into_raw(Box::new(Foo(Cell::new(3))));
+    /// let foo = unsafe { PtrWith::with_fn(raw, foo_close) }.unwrap();
+    /// foo.0.set(4);
+    /// drop(foo);
+    /// assert_eq!(CLOSED.with(Cell::get), 4);
+    ///
+    /// let raw = Box::into_raw(Box::new(Foo(Cell::new(3))));
+    /// drop(unsafe { PtrWith::with_fn(raw, foo_abort) });
+    /// assert_eq!(CLOSED.with(Cell::get), -1);
+    ///
+    /// extern "C" {
+    ///     fn free(ptr: *mut std::os::raw::c_void);
+    /// }
+    ///
+    /// let raw = unsafe { libc::malloc(16) };
+    /// drop(unsafe { PtrWith::with_fn(raw, free) }.unwrap());
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`PtrWith::new`].
+    pub unsafe fn with_fn(
+        raw: *mut T,
+        deleter: unsafe extern "C" fn(*mut T),
+    ) -> Option<PtrWith<T, unsafe extern "C" fn(*mut T)>> {
+        PtrWith::new(raw, deleter)
+    }
+}
+
+impl<T, D: Deleter<T>> PtrWith<T, D> {
+    /// Take ownership of a raw pointer, returning `None` if it is null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to `T` which can be freed
+    /// with `deleter`, and must not be owned by anything else.
+    pub unsafe fn new(raw: *mut T, deleter: D) -> Option<PtrWith<T, D>> {
+        NonNull::new(raw).map(|raw| PtrWith { raw, deleter })
+    }
+
+    /// Take ownership of a raw pointer, failing with [`NullPointer`] if it is
+    /// null.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`PtrWith::new`].
+    pub unsafe fn try_from_raw(raw: *mut T, deleter: D) -> Result<PtrWith<T, D>, NullPointer> {
+        PtrWith::new(raw, deleter).ok_or(NullPointer)
+    }
+
+    /// Take ownership of a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid, non-null pointer to `T` which can be freed with
+    /// `deleter`, and must not be owned by anything else.
+    pub unsafe fn from_raw(raw: *mut T, deleter: D) -> PtrWith<T, D> {
+        debug_assert!(
+            !raw.is_null(),
+            "PtrWith::from_raw called with a null pointer"
+        );
+        PtrWith {
+            raw: NonNull::new_unchecked(raw),
+            deleter,
+        }
+    }
+
+    pub fn into_raw(ptr: PtrWith<T, D>) -> *mut T {
+        PtrWith::into_raw_parts(ptr).0
+    }
+
+    /// Release ownership of the pointer, returning it together with its
+    /// deleter.
+    pub fn into_raw_parts(ptr: PtrWith<T, D>) -> (*mut T, D) {
+        let raw = ptr.raw.as_ptr();
+        let deleter = unsafe { ptr::read(&ptr.deleter) };
+        mem::forget(ptr);
+        (raw, deleter)
+    }
+
+    pub fn as_ptr(ptr: &PtrWith<T, D>) -> *const T {
+        ptr.raw.as_ptr()
+    }
+
+    pub fn as_raw(ptr: &mut PtrWith<T, D>) -> *mut T {
+        ptr.raw.as_ptr()
+    }
+
+    pub fn deleter(ptr: &PtrWith<T, D>) -> &D {
+        &ptr.deleter
+    }
+}
+
+impl<T: Alloc> From<Ptr<T>> for PtrWith<T> {
+    fn from(ptr: Ptr<T>) -> PtrWith<T> {
+        unsafe { PtrWith::from_raw(Ptr::into_raw(ptr), AllocDeleter) }
+    }
+}
+
+impl<T, D: Deleter<T>> AsRef<T> for PtrWith<T, D> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T, D: Deleter<T>> AsMut<T> for PtrWith<T, D> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T, D: Deleter<T>> Borrow<T> for PtrWith<T, D> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T, D: Deleter<T>> BorrowMut<T> for PtrWith<T, D> {
+    fn borrow_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T, D: Deleter<T>> Deref for PtrWith<T, D> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.raw.as_ref() }
+    }
+}
+
+impl<T, D: Deleter<T>> DerefMut for PtrWith<T, D> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.raw.as_mut() }
+    }
+}
+
+impl<T, D: Deleter<T>> Drop for PtrWith<T, D> {
+    fn drop(&mut self) {
+        self.deleter.delete(self.raw.as_ptr());
+    }
+}
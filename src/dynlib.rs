@@ -0,0 +1,643 @@
+//! Run-time loading of dynamic libraries.
+//!
+//! Instead of linking a C library through `extern "C"` blocks, it can be
+//! loaded at run time with [`Library::open`], and its functions looked up with
+//! [`Library::symbol`], or all at once with a struct declared with
+//! [`symbol_table!`].
+//!
+//! ```
+//! use std::os::raw::{c_char, c_int};
+//! use cffi::dynlib::Library;
+//!
+//! cffi::symbol_table! {
+//!     struct LibC<'lib> {
+//!         abs: unsafe extern "C" fn(c_int) -> c_int,
+//!         strlen: unsafe extern "C" fn(*const c_char) -> usize,
+//!     }
+//! }
+//!
+//! let library = Library::this().unwrap();
+//! let libc = unsafe { LibC::load(&library) }.unwrap();
+//! assert_eq!(unsafe { (*libc.abs)(-3) }, 3);
+//! assert_eq!(unsafe { (*libc.strlen)(b"four\0".as_ptr() as *const c_char) }, 4);
+//! ```
+//!
+//! Missing symbols are reported all together:
+//!
+//! ```
+//! # use cffi::dynlib::Library;
+//! cffi::symbol_table! {
+//!     struct Missing<'lib> {
+//!         abs: unsafe extern "C" fn(i32) -> i32,
+//!         cffi_missing_one: unsafe extern "C" fn(),
+//!         cffi_missing_two: unsafe extern "C" fn(),
+//!     }
+//! }
+//!
+//! let library = Library::this().unwrap();
+//! let error = unsafe { Missing::load(&library) }.err().unwrap();
+//! assert!(error.to_string().starts_with("missing symbols cffi_missing_one, cffi_missing_two"));
+//! ```
+//!
+//! Symbols may also be optional, have fallbacks (for example a newer
+//! `foo_new2` falling back to an older `foo_new`), and be looked up by
+//! version:
+//!
+//! ```
+//! # use cffi::dynlib::Library;
+//! use std::os::raw::c_int;
+//!
+//! cffi::symbol_table! {
+//!     struct Compat<'lib> {
+//!         abs: unsafe extern "C" fn(c_int) -> c_int = "cffi_abs2" | "abs",
+//!         optional labs: unsafe extern "C" fn(i64) -> i64,
+//!         optional missing: unsafe extern "C" fn() = "cffi_missing" | "cffi_missing" @ "V2",
+//!     }
+//! }
+//!
+//! let library = Library::this().unwrap();
+//! let compat = unsafe { Compat::load(&library) }.unwrap();
+//! assert_eq!(unsafe { (*compat.abs)(-3) }, 3);
+//! assert_eq!(unsafe { (*compat.labs.get().unwrap())(-3) }, 3);
+//! assert!(compat.missing.get().is_none());
+//! assert_eq!(compat.capabilities(), [("labs", true), ("missing", false)]);
+//! ```
+//!
+//! [`symbol_table!`]: crate::symbol_table
+
+use std::{
+    error,
+    ffi::{CStr, CString, OsStr},
+    fmt,
+    marker::PhantomData,
+    mem,
+    ops::Deref,
+    os::{raw::c_void, unix::ffi::OsStrExt},
+    ptr,
+    sync::OnceLock,
+};
+
+use crate::{Alloc, Movable, Private, Ptr, ThreadSafe};
+
+/// Dynamically loaded library.
+///
+/// The library is unloaded when its owning [`Ptr`] is dropped, and symbols
+/// looked up in it borrow it so that they can't outlive it.
+pub struct Library(Private);
+
+impl Library {
+    /// Load a library, resolving all its symbols immediately.
+    ///
+    /// ```
+    /// # #[cfg(target_os = "linux")] {
+    /// use cffi::dynlib::Library;
+    ///
+    /// let libm = Library::open("libm.so.6").unwrap();
+    /// let cos = unsafe { libm.symbol::<unsafe extern "C" fn(f64) -> f64>("cos") }.unwrap();
+    /// assert_eq!(unsafe { (*cos)(0.0) }, 1.0);
+    ///
+    /// assert!(Library::open("libcffi-nonexistent.so").is_err());
+    /// # }
+    /// ```
+    pub fn open<P: AsRef<OsStr>>(path: P) -> Result<Ptr<Library>, Error> {
+        Library::open_with_flags(path, libc::RTLD_NOW | libc::RTLD_LOCAL)
+    }
+
+    /// Load a library with the given `dlopen` flags.
+    pub fn open_with_flags<P: AsRef<OsStr>>(path: P, flags: i32) -> Result<Ptr<Library>, Error> {
+        let path = CString::new(path.as_ref().as_bytes()).map_err(|_| Error::Open {
+            message: "library path contains a nul byte".to_string(),
+        })?;
+        Library::dlopen(path.as_ptr(), flags)
+    }
+
+    /// Get a handle to the program itself, including all libraries loaded
+    /// into its global namespace.
+    pub fn this() -> Result<Ptr<Library>, Error> {
+        Library::dlopen(ptr::null(), libc::RTLD_NOW)
+    }
+
+    fn dlopen(path: *const libc::c_char, flags: i32) -> Result<Ptr<Library>, Error> {
+        let raw = unsafe { libc::dlopen(path, flags) } as *mut Library;
+        unsafe { Ptr::new(raw) }.ok_or_else(|| Error::Open {
+            message: last_error(),
+        })
+    }
+
+    /// Look up a symbol.
+    ///
+    /// # Safety
+    ///
+    /// `F` must be the correct type of the symbol, usually an
+    /// `unsafe extern "C" fn` pointer.
+    pub unsafe fn symbol<F: Copy>(&self, name: &str) -> Result<Symbol<'_, F>, Error> {
+        self.symbol_any(&[Candidate::new(name)])
+    }
+
+    /// Look up a specific version of a symbol.
+    ///
+    /// Versioned lookups are only supported on platforms providing `dlvsym`,
+    /// and fail elsewhere.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`Library::symbol`].
+    pub unsafe fn symbol_versioned<F: Copy>(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Symbol<'_, F>, Error> {
+        self.symbol_any(&[Candidate::new(name).version(version)])
+    }
+
+    /// Look up the first of `candidates` which is present in this library.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`Library::symbol`], for all candidates.
+    pub unsafe fn symbol_any<F: Copy>(
+        &self,
+        candidates: &[Candidate<'_>],
+    ) -> Result<Symbol<'_, F>, Error> {
+        let mut resolver = Resolver::new(self);
+        let symbol = resolver.resolve(candidates);
+        resolver.finish()?;
+        Ok(symbol.expect("resolver reported no errors"))
+    }
+
+    /// Look up a single candidate, returning null if it can't be found.
+    unsafe fn lookup(&self, candidate: &Candidate<'_>) -> *mut c_void {
+        let name = match CString::new(candidate.name) {
+            Ok(name) => name,
+            Err(_) => return ptr::null_mut(),
+        };
+
+        libc::dlerror();
+        match candidate.version {
+            None => libc::dlsym(self.as_raw(), name.as_ptr()),
+            Some(version) => match CString::new(version) {
+                Ok(version) => dlvsym(self.as_raw(), name.as_ptr(), version.as_ptr()),
+                Err(_) => ptr::null_mut(),
+            },
+        }
+    }
+
+    fn as_raw(&self) -> *mut c_void {
+        self as *const Library as *mut c_void
+    }
+}
+
+// Library handles are reference counted by the dynamic loader, which is
+// thread-safe.
+unsafe impl Movable for Library {}
+unsafe impl ThreadSafe for Library {}
+
+impl Alloc for Library {
+    fn free(this: *mut Self) {
+        unsafe { libc::dlclose(this as *mut c_void) };
+    }
+}
+
+#[cfg(any(
+    all(target_os = "linux", target_env = "gnu"),
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+))]
+use libc::dlvsym;
+
+#[cfg(not(any(
+    all(target_os = "linux", target_env = "gnu"),
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+)))]
+unsafe fn dlvsym(_: *mut c_void, _: *const libc::c_char, _: *const libc::c_char) -> *mut c_void {
+    ptr::null_mut()
+}
+
+/// Name, and optionally version, under which a symbol is looked up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Candidate<'a> {
+    pub name: &'a str,
+    pub version: Option<&'a str>,
+}
+
+impl<'a> Candidate<'a> {
+    pub const fn new(name: &'a str) -> Candidate<'a> {
+        Candidate {
+            name,
+            version: None,
+        }
+    }
+
+    pub const fn version(self, version: &'a str) -> Candidate<'a> {
+        Candidate {
+            name: self.name,
+            version: Some(version),
+        }
+    }
+}
+
+impl fmt::Display for Candidate<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.version {
+            None => f.write_str(self.name),
+            Some(version) => write!(f, "{}@{}", self.name, version),
+        }
+    }
+}
+
+/// Symbol looked up in a [`Library`].
+///
+/// This dereferences to the symbol's value, usually a function pointer, which
+/// is only accessible while the library is loaded.
+pub struct Symbol<'lib, F> {
+    raw: *mut c_void,
+    _marker: PhantomData<(&'lib Library, F)>,
+}
+
+impl<F> Symbol<'_, F> {
+    pub fn as_raw(&self) -> *mut c_void {
+        self.raw
+    }
+}
+
+// Symbols are addresses within a loaded library, which can be used from any
+// thread as long as the value they point to can.
+unsafe impl<F: Send> Send for Symbol<'_, F> {}
+unsafe impl<F: Sync> Sync for Symbol<'_, F> {}
+
+impl<F> Clone for Symbol<'_, F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F> Copy for Symbol<'_, F> {}
+
+impl<F> Deref for Symbol<'_, F> {
+    type Target = F;
+
+    fn deref(&self) -> &F {
+        unsafe { &*(&self.raw as *const *mut c_void as *const F) }
+    }
+}
+
+impl<F> fmt::Debug for Symbol<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Symbol").field(&self.raw).finish()
+    }
+}
+
+/// Optional symbol, looked up on first use.
+///
+/// The first candidate present in the library is used, and the result of the
+/// lookup is cached, so that it is performed at most once even when accessed
+/// from multiple threads.
+pub struct LazySymbol<'lib, F> {
+    library: &'lib Library,
+    candidates: &'static [Candidate<'static>],
+    symbol: OnceLock<Option<(Symbol<'lib, F>, Candidate<'static>)>>,
+}
+
+impl<'lib, F: Copy> LazySymbol<'lib, F> {
+    /// # Safety
+    ///
+    /// Same as for [`Library::symbol`], for all candidates.
+    pub unsafe fn new(
+        library: &'lib Library,
+        candidates: &'static [Candidate<'static>],
+    ) -> LazySymbol<'lib, F> {
+        LazySymbol {
+            library,
+            candidates,
+            symbol: OnceLock::new(),
+        }
+    }
+
+    /// Get the symbol, or `None` if no candidate is present in the library.
+    pub fn get(&self) -> Option<Symbol<'lib, F>> {
+        self.resolved().map(|(symbol, _)| symbol)
+    }
+
+    /// Returns `true` if any candidate is present in the library.
+    pub fn is_available(&self) -> bool {
+        self.resolved().is_some()
+    }
+
+    /// Get the candidate under which the symbol was found.
+    pub fn candidate(&self) -> Option<Candidate<'static>> {
+        self.resolved().map(|(_, candidate)| candidate)
+    }
+
+    fn resolved(&self) -> Option<(Symbol<'lib, F>, Candidate<'static>)> {
+        *self.symbol.get_or_init(|| {
+            let mut resolver = Resolver::new(self.library);
+            unsafe { resolver.resolve_with_candidate(self.candidates) }
+        })
+    }
+}
+
+unsafe impl<F: Send + Sync> Send for LazySymbol<'_, F> {}
+unsafe impl<F: Send + Sync> Sync for LazySymbol<'_, F> {}
+
+impl<F> fmt::Debug for LazySymbol<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LazySymbol")
+            .field("candidates", &self.candidates)
+            .finish()
+    }
+}
+
+/// Error loading a library or looking up its symbols.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The library could not be loaded.
+    Open { message: String },
+    /// One or more symbols could not be found.
+    Symbols { names: Vec<String>, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Open { message } => write!(f, "could not load library: {}", message),
+            Error::Symbols { names, message } if names.len() == 1 => {
+                write!(f, "missing symbol {}: {}", names[0], message)
+            }
+            Error::Symbols { names, message } => {
+                write!(f, "missing symbols {}: {}", names.join(", "), message)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// Symbol lookup collecting errors, used by [`symbol_table!`].
+///
+/// [`symbol_table!`]: crate::symbol_table
+#[doc(hidden)]
+pub struct Resolver<'lib> {
+    library: &'lib Library,
+    missing: Vec<String>,
+    message: Option<String>,
+}
+
+impl<'lib> Resolver<'lib> {
+    pub fn new(library: &'lib Library) -> Resolver<'lib> {
+        Resolver {
+            library,
+            missing: Vec::new(),
+            message: None,
+        }
+    }
+
+    /// Look up the first present candidate, recording the symbol as missing
+    /// if there is none.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`Library::symbol`], for all candidates.
+    pub unsafe fn resolve<F: Copy>(
+        &mut self,
+        candidates: &[Candidate<'_>],
+    ) -> Option<Symbol<'lib, F>> {
+        self.resolve_with_candidate(candidates)
+            .map(|(symbol, _)| symbol)
+    }
+
+    unsafe fn resolve_with_candidate<'c, F: Copy>(
+        &mut self,
+        candidates: &[Candidate<'c>],
+    ) -> Option<(Symbol<'lib, F>, Candidate<'c>)> {
+        assert_eq!(
+            mem::size_of::<F>(),
+            mem::size_of::<*mut c_void>(),
+            "symbol type must be pointer-sized"
+        );
+
+        for candidate in candidates {
+            let raw = self.library.lookup(candidate);
+            if !raw.is_null() {
+                let symbol = Symbol {
+                    raw,
+                    _marker: PhantomData,
+                };
+                return Some((symbol, *candidate));
+            }
+        }
+
+        let names = candidates
+            .iter()
+            .map(Candidate::to_string)
+            .collect::<Vec<_>>();
+        self.missing.push(names.join(" | "));
+        if self.message.is_none() {
+            self.message = Some(last_error());
+        }
+        None
+    }
+
+    /// Fail if any symbols were missing.
+    pub fn finish(self) -> Result<(), Error> {
+        if self.missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Symbols {
+                names: self.missing,
+                message: self.message.unwrap_or_default(),
+            })
+        }
+    }
+}
+
+fn last_error() -> String {
+    let message = unsafe { libc::dlerror() };
+    if message.is_null() {
+        "unknown error".to_string()
+    } else {
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Declare a struct of symbols looked up in a [`Library`].
+///
+/// The struct must have a single lifetime parameter, bounding the lifetime of
+/// its symbols. Fields are declared as `name: F`, where `F` is the symbol's
+/// type, optionally followed by `= "a" | "b" @ "VERSION" | ...` listing the
+/// names (and versions) under which it is looked up, in order of preference.
+/// By default it is looked up by the field's name.
+///
+/// Fields have type [`Symbol`]`<'lib, F>`, and are all looked up by the
+/// generated `load` function, which fails with an error naming all missing
+/// symbols. Fields preceded by `optional` have type
+/// [`LazySymbol`]`<'lib, F>` instead, and are only looked up on first use.
+/// The generated `capabilities` method reports which of them are present.
+///
+/// See the [module documentation](crate::dynlib) for examples.
+///
+/// [`Library`]: crate::dynlib::Library
+/// [`Symbol`]: crate::dynlib::Symbol
+/// [`LazySymbol`]: crate::dynlib::LazySymbol
+#[macro_export]
+macro_rules! symbol_table {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident<$lib:lifetime> {
+            $($fields:tt)*
+        }
+    ) => {
+        $crate::symbol_table!(
+            @parse [$(#[$attr])*] [$vis] $name $lib [] $($fields)*
+        );
+    };
+
+    (
+        @parse $attrs:tt $vis:tt $name:ident $lib:lifetime [$($parsed:tt)*]
+        $(#[$field_attr:meta])*
+        $field_vis:vis optional $field:ident: $type:ty
+        = $($symbol:literal $(@ $version:literal)?)|+
+        $(, $($rest:tt)*)?
+    ) => {
+        $crate::symbol_table!(
+            @parse $attrs $vis $name $lib [$($parsed)* {
+                optional [$(#[$field_attr])*] [$field_vis] $field [$type]
+                [$($crate::dynlib::Candidate::new($symbol) $(.version($version))?),+]
+            }]
+            $($($rest)*)?
+        );
+    };
+
+    (
+        @parse $attrs:tt $vis:tt $name:ident $lib:lifetime [$($parsed:tt)*]
+        $(#[$field_attr:meta])*
+        $field_vis:vis optional $field:ident: $type:ty
+        $(, $($rest:tt)*)?
+    ) => {
+        $crate::symbol_table!(
+            @parse $attrs $vis $name $lib [$($parsed)* {
+                optional [$(#[$field_attr])*] [$field_vis] $field [$type]
+                [$crate::dynlib::Candidate::new(stringify!($field))]
+            }]
+            $($($rest)*)?
+        );
+    };
+
+    (
+        @parse $attrs:tt $vis:tt $name:ident $lib:lifetime [$($parsed:tt)*]
+        $(#[$field_attr:meta])*
+        $field_vis:vis $field:ident: $type:ty
+        = $($symbol:literal $(@ $version:literal)?)|+
+        $(, $($rest:tt)*)?
+    ) => {
+        $crate::symbol_table!(
+            @parse $attrs $vis $name $lib [$($parsed)* {
+                required [$(#[$field_attr])*] [$field_vis] $field [$type]
+                [$($crate::dynlib::Candidate::new($symbol) $(.version($version))?),+]
+            }]
+            $($($rest)*)?
+        );
+    };
+
+    (
+        @parse $attrs:tt $vis:tt $name:ident $lib:lifetime [$($parsed:tt)*]
+        $(#[$field_attr:meta])*
+        $field_vis:vis $field:ident: $type:ty
+        $(, $($rest:tt)*)?
+    ) => {
+        $crate::symbol_table!(
+            @parse $attrs $vis $name $lib [$($parsed)* {
+                required [$(#[$field_attr])*] [$field_vis] $field [$type]
+                [$crate::dynlib::Candidate::new(stringify!($field))]
+            }]
+            $($($rest)*)?
+        );
+    };
+
+    (
+        @parse [$($attr:tt)*] [$vis:vis] $name:ident $lib:lifetime [$({
+            $kind:ident [$($field_attr:tt)*] [$field_vis:vis] $field:ident [$type:ty]
+            [$($candidate:expr),+]
+        })*]
+    ) => {
+        $($attr)*
+        $vis struct $name<$lib> {
+            $(
+                $($field_attr)*
+                $field_vis $field: $crate::symbol_table!(@type $kind $lib $type),
+            )*
+        }
+
+        impl<$lib> $name<$lib> {
+            /// Look up all symbols in `library`.
+            ///
+            /// # Safety
+            ///
+            /// All symbols must have the declared types.
+            #[allow(dead_code)]
+            $vis unsafe fn load(
+                library: &$lib $crate::dynlib::Library,
+            ) -> ::std::result::Result<Self, $crate::dynlib::Error> {
+                let mut resolver = $crate::dynlib::Resolver::new(library);
+                $(
+                    let $field = {
+                        const CANDIDATES: &[$crate::dynlib::Candidate<'static>] =
+                            &[$($candidate),+];
+                        $crate::symbol_table!(@load $kind library resolver CANDIDATES)
+                    };
+                )*
+                resolver.finish()?;
+                ::std::result::Result::Ok($name {
+                    $($field: $crate::symbol_table!(@unwrap $kind $field),)*
+                })
+            }
+
+            /// Report which optional symbols are present.
+            ///
+            /// This looks up all optional symbols which have not been used
+            /// yet.
+            #[allow(dead_code)]
+            $vis fn capabilities(&self) -> ::std::vec::Vec<(&'static str, bool)> {
+                let mut capabilities = ::std::vec::Vec::new();
+                $(
+                    $crate::symbol_table!(@capability $kind self $field capabilities);
+                )*
+                capabilities
+            }
+        }
+    };
+
+    (@type required $lib:lifetime $type:ty) => {
+        $crate::dynlib::Symbol<$lib, $type>
+    };
+
+    (@type optional $lib:lifetime $type:ty) => {
+        $crate::dynlib::LazySymbol<$lib, $type>
+    };
+
+    (@load required $library:ident $resolver:ident $candidates:ident) => {
+        $resolver.resolve($candidates)
+    };
+
+    (@load optional $library:ident $resolver:ident $candidates:ident) => {
+        $crate::dynlib::LazySymbol::new($library, $candidates)
+    };
+
+    (@unwrap required $field:ident) => {
+        $field.unwrap()
+    };
+
+    (@unwrap optional $field:ident) => {
+        $field
+    };
+
+    (@capability required $this:ident $field:ident $capabilities:ident) => {};
+
+    (@capability optional $this:ident $field:ident $capabilities:ident) => {
+        $capabilities.push((stringify!($field), $this.$field.is_available()));
+    };
+}
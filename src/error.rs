@@ -0,0 +1,491 @@
+//! Translation of C error reporting conventions into [`Result`]s.
+//!
+//! C functions report failure in many different ways: by returning `-1` and
+//! setting `errno`, by returning a negated `errno` value, by returning a
+//! non-zero status, or by returning a null pointer. Each such convention is
+//! represented by a type implementing [`ErrorCode`], and [`check`] uses it to
+//! convert a function's return value into a `Result`.
+//!
+//! Errors reported through error objects returned via an out-parameter are
+//! handled by [`ErrorOut`] instead, and errors which have to be fetched with a
+//! separate call by [`LastError`].
+//!
+//! ```
+//! use cffi::error::{check, Errno, NegativeErrno, Status, ZeroIsSuccess};
+//!
+//! let fd = check(Errno, unsafe { libc::dup(0) }).unwrap();
+//! assert_eq!(check(Errno, unsafe { libc::close(fd) }).unwrap(), 0);
+//!
+//! let error = check(Errno, unsafe { libc::close(-1) }).unwrap_err();
+//! assert_eq!(error.raw_os_error(), Some(libc::EBADF));
+//!
+//! let error = check(NegativeErrno, -libc::ENOENT).unwrap_err();
+//! assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+//!
+//! assert_eq!(check(ZeroIsSuccess, 3i32).unwrap_err(), Status(3));
+//! ```
+
+use std::{
+    borrow::Cow, convert::TryFrom, error::Error, fmt, io, mem, ops::Deref, os::raw::c_int, ptr,
+};
+
+use crate::{Alloc, NullPointer, Ptr};
+
+/// Convention for reporting errors through return values of type `T`.
+pub trait ErrorCode<T> {
+    type Error;
+
+    /// Returns `true` if `value` indicates success.
+    fn is_success(&self, value: &T) -> bool;
+
+    /// Convert a value indicating failure into an error.
+    ///
+    /// This is called immediately after the C function returns, so it may
+    /// inspect thread-local error state such as `errno`.
+    fn to_error(&self, value: T) -> Self::Error;
+}
+
+/// Check a C function's return value according to a convention.
+pub fn check<T, C: ErrorCode<T>>(convention: C, value: T) -> Result<T, C::Error> {
+    if convention.is_success(&value) {
+        Ok(value)
+    } else {
+        Err(convention.to_error(value))
+    }
+}
+
+/// Any predicate is a convention whose error is the returned value itself.
+impl<T, F: Fn(&T) -> bool> ErrorCode<T> for F {
+    type Error = T;
+
+    fn is_success(&self, value: &T) -> bool {
+        self(value)
+    }
+
+    fn to_error(&self, value: T) -> T {
+        value
+    }
+}
+
+/// Failure is indicated by `-1` (or any negative value), or by a null
+/// pointer, with the cause stored in `errno`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Errno;
+
+/// Failure is indicated by a negative value, which is the negation of an
+/// `errno` value.
+///
+/// Values which can't be negated into a `c_int` produce an error of kind
+/// [`Other`](io::ErrorKind::Other) instead.
+///
+/// ```
+/// use std::io::ErrorKind;
+/// use cffi::error::{check, NegativeErrno};
+///
+/// let error = check(NegativeErrno, -(libc::ENOENT as i64)).unwrap_err();
+/// assert_eq!(error.raw_os_error(), Some(libc::ENOENT));
+///
+/// assert_eq!(check(NegativeErrno, i32::MIN).unwrap_err().kind(), ErrorKind::Other);
+/// assert_eq!(check(NegativeErrno, i64::MIN).unwrap_err().kind(), ErrorKind::Other);
+/// assert_eq!(check(NegativeErrno, -(1i64 << 40)).unwrap_err().kind(), ErrorKind::Other);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NegativeErrno;
+
+/// Success is indicated by zero, and any other value is an error status.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ZeroIsSuccess;
+
+/// Non-zero status returned by a function following [`ZeroIsSuccess`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Status<T>(pub T);
+
+impl<T: fmt::Display> fmt::Display for Status<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "operation failed with status {}", self.0)
+    }
+}
+
+impl<T: fmt::Debug + fmt::Display> Error for Status<T> {}
+
+macro_rules! signed_conventions {
+    ($($type:ty),*) => {
+        $(
+            impl ErrorCode<$type> for Errno {
+                type Error = io::Error;
+
+                fn is_success(&self, value: &$type) -> bool {
+                    *value >= 0
+                }
+
+                fn to_error(&self, _: $type) -> io::Error {
+                    io::Error::last_os_error()
+                }
+            }
+
+            impl ErrorCode<$type> for NegativeErrno {
+                type Error = io::Error;
+
+                fn is_success(&self, value: &$type) -> bool {
+                    *value >= 0
+                }
+
+                fn to_error(&self, value: $type) -> io::Error {
+                    match value.checked_neg().map(c_int::try_from) {
+                        Some(Ok(errno)) => io::Error::from_raw_os_error(errno),
+                        _ => io::Error::new(
+                            io::ErrorKind::Other,
+                            format!("invalid negative errno {}", value),
+                        ),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+signed_conventions!(i8, i16, i32, i64, isize);
+
+macro_rules! status_conventions {
+    ($($type:ty),*) => {
+        $(
+            impl ErrorCode<$type> for ZeroIsSuccess {
+                type Error = Status<$type>;
+
+                fn is_success(&self, value: &$type) -> bool {
+                    *value == 0
+                }
+
+                fn to_error(&self, value: $type) -> Status<$type> {
+                    Status(value)
+                }
+            }
+        )*
+    };
+}
+
+status_conventions!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl<T> ErrorCode<*mut T> for Errno {
+    type Error = io::Error;
+
+    fn is_success(&self, value: &*mut T) -> bool {
+        !value.is_null()
+    }
+
+    fn to_error(&self, _: *mut T) -> io::Error {
+        io::Error::last_os_error()
+    }
+}
+
+impl<T> ErrorCode<*const T> for Errno {
+    type Error = io::Error;
+
+    fn is_success(&self, value: &*const T) -> bool {
+        !value.is_null()
+    }
+
+    fn to_error(&self, _: *const T) -> io::Error {
+        io::Error::last_os_error()
+    }
+}
+
+/// Failure is indicated by a null pointer, with no further information.
+impl<T> ErrorCode<*mut T> for NullPointer {
+    type Error = NullPointer;
+
+    fn is_success(&self, value: &*mut T) -> bool {
+        !value.is_null()
+    }
+
+    fn to_error(&self, _: *mut T) -> NullPointer {
+        NullPointer
+    }
+}
+
+impl<T: Alloc> Ptr<T> {
+    /// Take ownership of a raw pointer, converting a null pointer into an
+    /// error according to `convention`.
+    ///
+    /// ```
+    /// use cffi::{error::Errno, Alloc, Ptr};
+    ///
+    /// struct File(libc::FILE);
+    ///
+    /// impl Alloc for File {
+    ///     fn free(this: *mut Self) {
+    ///         unsafe { libc::fclose(this as *mut libc::FILE) };
+    ///     }
+    /// }
+    ///
+    /// let path = b"/nonexistent/file\0".as_ptr() as *const _;
+    /// let raw = unsafe { libc::fopen(path, b"r\0".as_ptr() as *const _) } as *mut File;
+    /// let error = unsafe { Ptr::new_checked(raw, Errno) }.err().unwrap();
+    /// assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`Ptr::new`].
+    pub unsafe fn new_checked<C>(raw: *mut T, convention: C) -> Result<Ptr<T>, C::Error>
+    where
+        C: ErrorCode<*mut T>,
+    {
+        check(convention, raw).map(|raw| Ptr::from_raw(raw))
+    }
+}
+
+/// Error object allocated by a C library.
+///
+/// Libraries such as GLib report errors through objects returned via an
+/// `err**` out-parameter. Implementing this trait for such an object's type
+/// lets it be wrapped in a [`CError`], which implements [`Error`].
+pub trait ErrorObject: Alloc {
+    /// Human-readable description of the error.
+    fn message(&self) -> Cow<'_, str>;
+
+    /// Numeric error code, if the library provides one.
+    fn code(&self) -> Option<i64> {
+        None
+    }
+}
+
+/// Owned error object, implementing [`Error`].
+pub struct CError<E: ErrorObject>(Ptr<E>);
+
+impl<E: ErrorObject> CError<E> {
+    pub fn new(error: Ptr<E>) -> CError<E> {
+        CError(error)
+    }
+
+    pub fn into_inner(error: CError<E>) -> Ptr<E> {
+        error.0
+    }
+}
+
+impl<E: ErrorObject> From<Ptr<E>> for CError<E> {
+    fn from(error: Ptr<E>) -> CError<E> {
+        CError(error)
+    }
+}
+
+impl<E: ErrorObject> Deref for CError<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        &self.0
+    }
+}
+
+impl<E: ErrorObject> fmt::Debug for CError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CError")
+            .field("code", &self.code())
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+impl<E: ErrorObject> fmt::Display for CError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code() {
+            Some(code) => write!(f, "{} (code {})", self.message(), code),
+            None => f.write_str(&self.message()),
+        }
+    }
+}
+
+impl<E: ErrorObject> Error for CError<E> {}
+
+/// Out-parameter receiving an error object.
+///
+/// This manages the `err**` argument of functions reporting errors through
+/// error objects, producing them as owned [`Ptr`]s. If the error is never
+/// taken, it is freed on drop. Error objects implementing [`ErrorObject`] can
+/// then be wrapped in a [`CError`] to be used as an [`Error`].
+///
+/// ```
+/// use std::{borrow::Cow, ffi::CStr, os::raw::{c_char, c_int}};
+/// use cffi::{error::{CError, ErrorObject, ErrorOut}, Alloc};
+///
+/// #[repr(C)]
+/// struct FooError {
+///     code: c_int,
+///     message: *const c_char,
+/// }
+///
+/// impl Alloc for FooError {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// impl ErrorObject for FooError {
+///     fn message(&self) -> Cow<'_, str> {
+///         unsafe { CStr::from_ptr(self.message) }.to_string_lossy()
+///     }
+///
+///     fn code(&self) -> Option<i64> {
+///         Some(self.code.into())
+///     }
+/// }
+///
+/// unsafe extern "C" fn foo_parse(value: c_int, err: *mut *mut FooError) -> c_int {
+///     if value < 0 {
+///         let message = b"negative value\0".as_ptr() as *const c_char;
+///         *err = Box::into_raw(Box::new(FooError { code: 7, message }));
+///         return 0;
+///     }
+///     value
+/// }
+///
+/// assert_eq!(ErrorOut::call(|err| unsafe { foo_parse(3, err) }).ok(), Some(3));
+///
+/// let error = ErrorOut::call(|err| unsafe { foo_parse(-1, err) }).err().unwrap();
+/// assert_eq!(error.code, 7);
+///
+/// let error = ErrorOut::call(|err| unsafe { foo_parse(-1, err) }).map_err(CError::new);
+/// assert_eq!(error.unwrap_err().to_string(), "negative value (code 7)");
+/// ```
+pub struct ErrorOut<E: Alloc>(*mut E);
+
+impl<E: Alloc> ErrorOut<E> {
+    pub fn new() -> ErrorOut<E> {
+        ErrorOut(ptr::null_mut())
+    }
+
+    /// Call `function` with an error out-parameter, returning its result if
+    /// no error was reported.
+    pub fn call<T, F>(function: F) -> Result<T, Ptr<E>>
+    where
+        F: FnOnce(*mut *mut E) -> T,
+    {
+        let mut out = ErrorOut::new();
+        let value = function(out.as_out());
+        match out.take() {
+            Some(error) => Err(error),
+            None => Ok(value),
+        }
+    }
+
+    /// Pointer to be passed as the `err**` argument.
+    pub fn as_out(&mut self) -> *mut *mut E {
+        &mut self.0
+    }
+
+    pub fn is_set(&self) -> bool {
+        !self.0.is_null()
+    }
+
+    /// Take ownership of the reported error, if any.
+    pub fn take(&mut self) -> Option<Ptr<E>> {
+        let raw = mem::replace(&mut self.0, ptr::null_mut());
+        unsafe { Ptr::new(raw) }
+    }
+}
+
+impl<E: Alloc> Default for ErrorOut<E> {
+    fn default() -> ErrorOut<E> {
+        ErrorOut::new()
+    }
+}
+
+impl<E: Alloc> Drop for ErrorOut<E> {
+    fn drop(&mut self) {
+        self.take();
+    }
+}
+
+/// Library which reports errors through separately retrieved error state.
+///
+/// Some libraries require calling a separate function immediately after a
+/// failure to fetch its cause, such as SQLite's `sqlite3_errmsg` or OpenSSL's
+/// `ERR_get_error`. This trait is implemented once per library (or per handle
+/// owning the error state, like an SQLite connection), and [`LastError::call`]
+/// fetches errors right after a failed call, before anything else can
+/// overwrite them.
+///
+/// ```
+/// use std::{cell::RefCell, iter, os::raw::c_int};
+/// use cffi::error::{LastError, LastErrors};
+///
+/// thread_local!(static QUEUE: RefCell<Vec<c_int>> = RefCell::new(Vec::new()));
+///
+/// extern "C" fn lib_frob(value: c_int) -> c_int {
+///     if value < 0 {
+///         QUEUE.with(|queue| queue.borrow_mut().extend([1, 2]));
+///         return 0;
+///     }
+///     1
+/// }
+///
+/// extern "C" fn lib_get_error() -> c_int {
+///     QUEUE.with(|queue| queue.borrow_mut().pop()).unwrap_or(0)
+/// }
+///
+/// struct Lib;
+///
+/// impl LastError for Lib {
+///     type Record = c_int;
+///
+///     fn take_errors(&self) -> Vec<c_int> {
+///         iter::from_fn(|| Some(lib_get_error()).filter(|&code| code != 0)).collect()
+///     }
+/// }
+///
+/// assert_eq!(Lib.call(|&ret: &c_int| ret == 1, || lib_frob(1)), Ok(1));
+/// assert_eq!(Lib.call(|&ret: &c_int| ret == 1, || lib_frob(-1)), Err(LastErrors(vec![2, 1])));
+/// ```
+pub trait LastError {
+    /// Information about a single error.
+    type Record;
+
+    /// Fetch and clear all pending errors.
+    fn take_errors(&self) -> Vec<Self::Record>;
+
+    /// Clear all pending errors.
+    fn clear(&self) {
+        self.take_errors();
+    }
+
+    /// Call `function`, checking its result according to `convention`, and
+    /// fetching pending errors if it failed.
+    ///
+    /// Errors left over from earlier calls are cleared first, so that they are
+    /// not attributed to this one.
+    fn call<T, C, F>(&self, convention: C, function: F) -> Result<T, LastErrors<Self::Record>>
+    where
+        C: ErrorCode<T>,
+        F: FnOnce() -> T,
+    {
+        self.clear();
+        let value = function();
+        if convention.is_success(&value) {
+            Ok(value)
+        } else {
+            Err(LastErrors(self.take_errors()))
+        }
+    }
+}
+
+/// Errors fetched through [`LastError`] after a failed call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LastErrors<R>(pub Vec<R>);
+
+impl<R: fmt::Display> fmt::Display for LastErrors<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut records = self.0.iter();
+        match records.next() {
+            None => f.write_str("operation failed with no error reported"),
+            Some(first) => {
+                write!(f, "{}", first)?;
+                for record in records {
+                    write!(f, "; {}", record)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<R: fmt::Debug + fmt::Display> Error for LastErrors<R> {}
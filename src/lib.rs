@@ -1,9 +1,50 @@
 use std::{
     borrow::{Borrow, BorrowMut},
+    error::Error,
+    fmt,
+    marker::{PhantomData, PhantomPinned},
     mem,
     ops::{Deref, DerefMut},
+    ptr::NonNull,
 };
 
+pub mod buffer;
+#[cfg(unix)]
+pub mod dynlib;
+pub mod error;
+pub mod out;
+
+mod array;
+mod borrowed;
+mod callback;
+mod child;
+mod close;
+mod deleter;
+mod list;
+mod null_terminated;
+mod panic;
+mod registration;
+mod scope;
+mod serialized;
+mod shared;
+mod string;
+
+pub use array::CArray;
+pub use borrowed::{Ref, RefMut};
+pub use callback::{Callback, CallbackMut, CallbackOnce, Trampoline};
+pub use cffi_derive::opaque;
+pub use child::{ChildPtr, SharedChildPtr};
+pub use close::{Closer, DropPolicy, TryFree};
+pub use deleter::{AllocDeleter, Deleter, LibcFree, PtrWith};
+pub use list::{Linked, LinkedList, LinkedListIter};
+pub use null_terminated::{Element, NullTerminated, NullTerminatedIter, OwnedNullTerminated};
+pub use panic::{catch_unwind_ffi, resume_pending_panic, take_pending_panic, Abort, Sentinel};
+pub use registration::Registration;
+pub use scope::Scope;
+pub use serialized::{Locked, LockedGuard, Serialized, SerializedGuard};
+pub use shared::{AtomicShared, Shared};
+pub use string::CStrPtr;
+
 /// Marker for private C structures.
 ///
 /// It is a common practice in C not to hide contents of types by defining them
@@ -25,15 +66,17 @@ use std::{
 ///
 /// the bindings would look as follows
 ///
-/// ```
+/// ```no_run
+/// use cffi::Ptr;
+///
 /// pub struct Foo(cffi::Private);
 ///
 /// impl Foo {
-///     fn new() -> cffi::Ptr<Foo> {
-///         Ptr::new(unsafe { foo_new() })
+///     fn new() -> Option<Ptr<Foo>> {
+///         unsafe { Ptr::new(foo_new()) }
 ///     }
 ///
-///     fn use(&mut self) {
+///     fn use_it(&mut self) {
 ///         unsafe { foo_use(self) }
 ///     }
 /// }
@@ -50,8 +93,24 @@ use std::{
 ///     fn foo_use(foo: *mut Foo);
 /// }
 /// ```
+///
+/// The same declaration of `Foo` can also be generated with [`opaque`]:
+///
+/// ```no_run
+/// #[cffi::opaque(free = foo_delete)]
+/// pub struct Foo;
+///
+/// extern "C" {
+///     fn foo_delete(foo: *mut Foo);
+/// }
+/// ```
+///
+/// Types containing `Private` are neither [`Send`], [`Sync`] nor [`Unpin`],
+/// since nothing is known about how the C library uses them. Types which are
+/// safe to use from other threads should implement [`Movable`] and
+/// [`ThreadSafe`] instead, which make [`Ptr`] `Send` and `Sync`.
 #[repr(C)]
-pub struct Private(Never);
+pub struct Private(Never, PhantomData<(*mut u8, PhantomPinned)>);
 
 // TODO: replace with `!` once it's stabilized.
 enum Never {}
@@ -61,55 +120,165 @@ pub trait Alloc {
     fn free(this: *mut Self);
 }
 
+/// Trait for FFI types which are reference counted by their library.
+///
+/// This is the model used by `foo_ref`/`foo_unref` style APIs, and is what
+/// [`Shared`] and [`AtomicShared`] delegate to.
+pub trait Retain {
+    fn retain(this: *mut Self);
+    fn release(this: *mut Self);
+}
+
+/// Marker for [`Retain`] types whose reference count is updated atomically.
+///
+/// # Safety
+///
+/// Implementors guarantee that [`Retain::retain`] and [`Retain::release`] may
+/// be called concurrently from multiple threads on the same object.
+pub unsafe trait AtomicRetain: Retain {}
+
+/// Marker for FFI types which may be used from threads other than the one
+/// which created them.
+///
+/// This makes [`Ptr`] [`Send`], and corresponds to C libraries documenting
+/// that an object may be passed between threads, as long as it is only used
+/// by one of them at a time.
+///
+/// # Safety
+///
+/// Implementors guarantee that all operations on an object, including
+/// freeing it, may be performed on any thread.
+pub unsafe trait Movable {}
+
+/// Marker for FFI types which may be used from multiple threads at once.
+///
+/// This makes [`Ptr`] [`Sync`], and corresponds to C libraries documenting
+/// that an object is thread-safe.
+///
+/// # Safety
+///
+/// Implementors guarantee that all operations taking `&self` may be performed
+/// concurrently from multiple threads.
+pub unsafe trait ThreadSafe {}
+
+// C strings are plain bytes, which can be read and freed on any thread.
+unsafe impl Movable for std::ffi::CStr {}
+unsafe impl ThreadSafe for std::ffi::CStr {}
+
 /// Owned pointer.
 ///
 /// This type is very similar to [`Box`] in that it is essentially an owned
 /// pointer. The difference between them is that [`Box`] manages memory
 /// allocation itself, while `Ptr` delegates this to the pointee's [`Alloc`]
 /// implementation.
-pub struct Ptr<T: Alloc>(*mut T);
+///
+/// `Ptr` is never null, which means that `Option<Ptr<T>>` has the same size
+/// and representation as `*mut T`, and can be used directly in `extern "C"`
+/// signatures in place of a nullable pointer.
+///
+/// ```
+/// # struct Foo(cffi::Private);
+/// # impl cffi::Alloc for Foo { fn free(_: *mut Self) {} }
+/// use std::mem::size_of;
+///
+/// assert_eq!(size_of::<Option<cffi::Ptr<Foo>>>(), size_of::<*mut Foo>());
+/// assert!(unsafe { cffi::Ptr::<Foo>::new(std::ptr::null_mut()) }.is_none());
+/// ```
+///
+/// `Ptr<T>` is [`Send`] if `T` is [`Movable`], and [`Sync`] if `T` is
+/// [`ThreadSafe`]:
+///
+/// ```
+/// use cffi::{Alloc, Movable, Private, Ptr, ThreadSafe};
+///
+/// struct Foo(Private);
+///
+/// impl Alloc for Foo {
+///     fn free(_: *mut Self) {}
+/// }
+///
+/// unsafe impl Movable for Foo {}
+/// unsafe impl ThreadSafe for Foo {}
+///
+/// fn assert_thread_safe<T: Send + Sync>() {}
+/// assert_thread_safe::<Ptr<Foo>>();
+/// ```
+#[repr(transparent)]
+pub struct Ptr<T: Alloc>(NonNull<T>);
+
+unsafe impl<T: Alloc + Movable> Send for Ptr<T> {}
+unsafe impl<T: Alloc + ThreadSafe> Sync for Ptr<T> {}
 
 impl<T: Alloc> Ptr<T> {
+    /// Take ownership of a raw pointer, returning `None` if it is null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to `T` which can be freed
+    /// with `T`'s [`Alloc`] implementation, and must not be owned by anything
+    /// else.
+    pub unsafe fn new(raw: *mut T) -> Option<Ptr<T>> {
+        NonNull::new(raw).map(Ptr)
+    }
+
+    /// Take ownership of a raw pointer, failing with [`NullPointer`] if it is
+    /// null.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`Ptr::new`].
+    pub unsafe fn try_from_raw(raw: *mut T) -> Result<Ptr<T>, NullPointer> {
+        Ptr::new(raw).ok_or(NullPointer)
+    }
+
+    /// Take ownership of a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid, non-null pointer to `T` which can be freed with
+    /// `T`'s [`Alloc`] implementation, and must not be owned by anything else.
+    /// Use [`Ptr::new`] or [`Ptr::try_from_raw`] if the pointer may be null.
     pub unsafe fn from_raw(raw: *mut T) -> Ptr<T> {
-        Ptr(raw)
+        debug_assert!(!raw.is_null(), "Ptr::from_raw called with a null pointer");
+        Ptr(NonNull::new_unchecked(raw))
     }
 
     pub fn into_raw(ptr: Ptr<T>) -> *mut T {
-        let raw = ptr.0;
+        let raw = ptr.0.as_ptr();
         mem::forget(ptr);
         raw
     }
 
     pub fn as_ptr(ptr: &Ptr<T>) -> *const T {
-        ptr.0
+        ptr.0.as_ptr()
     }
 
     pub fn as_raw(ptr: &mut Ptr<T>) -> *mut T {
-        ptr.0
+        ptr.0.as_ptr()
     }
 }
 
 impl<T: Alloc> AsRef<T> for Ptr<T> {
     fn as_ref(&self) -> &T {
-        &**self
+        self
     }
 }
 
 impl<T: Alloc> AsMut<T> for Ptr<T> {
     fn as_mut(&mut self) -> &mut T {
-        &mut **self
+        self
     }
 }
 
 impl<T: Alloc> Borrow<T> for Ptr<T> {
     fn borrow(&self) -> &T {
-        &**self
+        self
     }
 }
 
 impl<T: Alloc> BorrowMut<T> for Ptr<T> {
     fn borrow_mut(&mut self) -> &mut T {
-        &mut **self
+        self
     }
 }
 
@@ -117,22 +286,39 @@ impl<T: Alloc> Deref for Ptr<T> {
     type Target = T;
 
     fn deref(&self) -> &T {
-        unsafe { mem::transmute(self.0) }
+        unsafe { self.0.as_ref() }
     }
 }
 
 impl<T: Alloc> DerefMut for Ptr<T> {
     fn deref_mut(&mut self) -> &mut T {
-        unsafe { mem::transmute(self.0) }
+        unsafe { self.0.as_mut() }
     }
 }
 
 impl<T: Alloc> Drop for Ptr<T> {
     fn drop(&mut self) {
-        Alloc::free(self.0);
+        Alloc::free(self.0.as_ptr());
     }
 }
 
+/// Error returned when a null pointer was passed where a non-null one was
+/// required.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NullPointer;
+
+impl fmt::Display for NullPointer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("unexpected null pointer")
+    }
+}
+
+impl Error for NullPointer {}
+
+/// Implement [`AsRef`], [`Deref`] and [`DerefMut`] for a newtype wrapper
+/// around a pointer.
+///
+/// See [`wrapper!`] for a more flexible version.
 #[macro_export]
 macro_rules! impl_ptr {
     ($wrapper:ty, $type:ty) => {
@@ -157,3 +343,209 @@ macro_rules! impl_ptr {
         }
     };
 }
+
+/// Generate trait implementations for a newtype wrapper around a pointer.
+///
+/// The wrapper must be a tuple struct whose first field is the pointer. It is
+/// declared as `impl[generics] Wrapper: Pointer => Target { options }`, where
+/// `generics` (which may be omitted along with the brackets) are the
+/// parameters of the generated impls, and `options` is a comma-separated list
+/// of:
+///
+/// - `AsRef`, `AsMut`, `Borrow`, `BorrowMut`, `Deref`, `DerefMut`: forward the
+///   trait to the pointer,
+/// - `Clone`: clone the pointer, e.g. to retain a [`Shared`],
+/// - `Debug`: format as the wrapper's name and the pointer's address,
+/// - `raw`: add `from_raw` and `into_raw` associated functions forwarding to
+///   the pointer's,
+/// - `unsafe Send`, `unsafe Sync`: unconditionally implement the marker trait.
+///
+/// ```
+/// use std::{borrow::{Borrow, BorrowMut}, fmt::Debug};
+/// use cffi::{Alloc, Ptr};
+///
+/// struct Foo {
+///     value: i32,
+/// }
+///
+/// impl Alloc for Foo {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) })
+///     }
+/// }
+///
+/// struct Wrapper(Ptr<Foo>);
+///
+/// cffi::wrapper! {
+///     impl Wrapper: Ptr<Foo> => Foo {
+///         AsRef, AsMut, Borrow, BorrowMut, Deref, DerefMut, Debug, raw,
+///         unsafe Send, unsafe Sync,
+///     }
+/// }
+///
+/// let mut wrapper = unsafe { Wrapper::from_raw(Box::into_raw(Box::new(Foo { value: 1 }))) };
+/// wrapper.value += 1;
+/// wrapper.as_mut().value += 1;
+/// BorrowMut::<Foo>::borrow_mut(&mut wrapper).value += 1;
+/// assert_eq!(wrapper.as_ref().value, 4);
+/// assert_eq!(Borrow::<Foo>::borrow(&wrapper).value, 4);
+/// assert!(format!("{:?}", wrapper).starts_with("Wrapper(0x"));
+///
+/// fn assert_thread_safe<T: Send + Sync>(_: &T) {}
+/// assert_thread_safe(&wrapper);
+///
+/// let raw = Wrapper::into_raw(wrapper);
+/// drop(unsafe { Wrapper::from_raw(raw) });
+/// ```
+///
+/// Wrappers can be generic, and wrap any pointer type:
+///
+/// ```
+/// use cffi::{Ref, Retain, Shared};
+///
+/// struct Foo {
+///     value: i32,
+/// }
+///
+/// impl Retain for Foo {
+///     fn retain(_: *mut Self) {}
+///     fn release(_: *mut Self) {}
+/// }
+///
+/// struct Handle(Shared<Foo>);
+/// struct Child<'a>(Ref<'a, Foo>);
+///
+/// cffi::wrapper! {
+///     impl Handle: Shared<Foo> => Foo { Clone, Deref }
+/// }
+///
+/// cffi::wrapper! {
+///     impl['a] Child<'a>: Ref<'a, Foo> => Foo { AsRef, Deref }
+/// }
+///
+/// let mut foo = Foo { value: 3 };
+/// let handle = Handle(unsafe { Shared::from_raw(&mut foo) });
+/// assert_eq!(handle.clone().value, 3);
+///
+/// let child = Child(unsafe { Ref::new(&handle, &mut foo) }.unwrap());
+/// assert_eq!(child.as_ref().value, 3);
+/// ```
+#[macro_export]
+macro_rules! wrapper {
+    (impl [$($generics:tt)*] $wrapper:ty: $ptr:ty => $target:ty { $($options:tt)* }) => {
+        $crate::wrapper!(@options [$($generics)*] ($wrapper, $ptr, $target) $($options)*);
+    };
+
+    (impl $wrapper:ty: $ptr:ty => $target:ty { $($options:tt)* }) => {
+        $crate::wrapper!(@options [] ($wrapper, $ptr, $target) $($options)*);
+    };
+
+    (@options [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty) $(,)?) => {};
+
+    (@options [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)
+        unsafe $option:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::wrapper!(@unsafe $option [$($generics)*] $wrapper);
+        $crate::wrapper!(@options [$($generics)*] ($wrapper, $ptr, $target) $($($rest)*)?);
+    };
+
+    (@options [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)
+        $option:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::wrapper!(@impl $option [$($generics)*] ($wrapper, $ptr, $target));
+        $crate::wrapper!(@options [$($generics)*] ($wrapper, $ptr, $target) $($($rest)*)?);
+    };
+
+    (@impl AsRef [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)) => {
+        impl<$($generics)*> ::std::convert::AsRef<$target> for $wrapper {
+            fn as_ref(&self) -> &$target {
+                &*self.0
+            }
+        }
+    };
+
+    (@impl AsMut [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)) => {
+        impl<$($generics)*> ::std::convert::AsMut<$target> for $wrapper {
+            fn as_mut(&mut self) -> &mut $target {
+                &mut *self.0
+            }
+        }
+    };
+
+    (@impl Borrow [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)) => {
+        impl<$($generics)*> ::std::borrow::Borrow<$target> for $wrapper {
+            fn borrow(&self) -> &$target {
+                &*self.0
+            }
+        }
+    };
+
+    (@impl BorrowMut [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)) => {
+        impl<$($generics)*> ::std::borrow::BorrowMut<$target> for $wrapper {
+            fn borrow_mut(&mut self) -> &mut $target {
+                &mut *self.0
+            }
+        }
+    };
+
+    (@impl Deref [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)) => {
+        impl<$($generics)*> ::std::ops::Deref for $wrapper {
+            type Target = $target;
+
+            fn deref(&self) -> &$target {
+                &*self.0
+            }
+        }
+    };
+
+    (@impl DerefMut [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)) => {
+        impl<$($generics)*> ::std::ops::DerefMut for $wrapper {
+            fn deref_mut(&mut self) -> &mut $target {
+                &mut *self.0
+            }
+        }
+    };
+
+    (@impl Clone [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)) => {
+        impl<$($generics)*> ::std::clone::Clone for $wrapper {
+            fn clone(&self) -> Self {
+                Self(::std::clone::Clone::clone(&self.0))
+            }
+        }
+    };
+
+    (@impl Debug [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)) => {
+        impl<$($generics)*> ::std::fmt::Debug for $wrapper {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.debug_tuple(stringify!($wrapper))
+                    .field(&(&*self.0 as *const $target))
+                    .finish()
+            }
+        }
+    };
+
+    (@impl raw [$($generics:tt)*] ($wrapper:ty, $ptr:ty, $target:ty)) => {
+        impl<$($generics)*> $wrapper {
+            /// Take ownership of a raw pointer.
+            ///
+            /// # Safety
+            ///
+            /// Same as for the wrapped pointer's `from_raw`.
+            pub unsafe fn from_raw(raw: *mut $target) -> Self {
+                Self(<$ptr>::from_raw(raw))
+            }
+
+            pub fn into_raw(this: Self) -> *mut $target {
+                <$ptr>::into_raw(this.0)
+            }
+        }
+    };
+
+    (@unsafe Send [$($generics:tt)*] $wrapper:ty) => {
+        unsafe impl<$($generics)*> ::std::marker::Send for $wrapper {}
+    };
+
+    (@unsafe Sync [$($generics:tt)*] $wrapper:ty) => {
+        unsafe impl<$($generics)*> ::std::marker::Sync for $wrapper {}
+    };
+}
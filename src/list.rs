@@ -0,0 +1,161 @@
+use std::{iter::FusedIterator, marker::PhantomData, ptr};
+
+use crate::{Alloc, Ptr};
+
+/// Node of an intrusive singly linked list.
+///
+/// Structures such as `struct addrinfo` or `struct ifaddrs` form lists through
+/// a `next` field, and are freed all at once through their head.
+pub trait Linked {
+    /// Pointer to the next node, or null if this is the last one.
+    fn next(&self) -> *mut Self;
+}
+
+/// Owned intrusive linked list.
+///
+/// The list owns its head, and is freed by passing it to [`Alloc::free`],
+/// which is expected to free all following nodes as well. An empty list
+/// (whose head is null) is never freed.
+///
+/// ```
+/// use cffi::{Alloc, Linked, LinkedList};
+///
+/// struct Node {
+///     value: i32,
+///     next: *mut Node,
+/// }
+///
+/// impl Linked for Node {
+///     fn next(&self) -> *mut Node {
+///         self.next
+///     }
+/// }
+///
+/// impl Alloc for Node {
+///     fn free(mut this: *mut Self) {
+///         while !this.is_null() {
+///             let node = unsafe { Box::from_raw(this) };
+///             this = node.next;
+///         }
+///     }
+/// }
+///
+/// let empty = unsafe { LinkedList::<Node>::new(std::ptr::null_mut()) };
+/// assert!(empty.is_empty());
+/// assert_eq!(empty.iter().count(), 0);
+///
+/// let single = Box::into_raw(Box::new(Node { value: 1, next: std::ptr::null_mut() }));
+/// let single = unsafe { LinkedList::new(single) };
+/// assert_eq!(single.iter().map(|node| node.value).collect::<Vec<_>>(), [1]);
+///
+/// let tail = Box::into_raw(Box::new(Node { value: 2, next: std::ptr::null_mut() }));
+/// let head = Box::into_raw(Box::new(Node { value: 1, next: tail }));
+/// let list = unsafe { LinkedList::new(head) };
+/// assert_eq!(list.len(), 2);
+/// assert_eq!(list.iter().map(|node| node.value).sum::<i32>(), 3);
+/// ```
+///
+/// Iteration panics when the list contains a cycle, instead of looping
+/// forever:
+///
+/// ```should_panic
+/// # use cffi::{Alloc, Linked, LinkedList};
+/// # struct Node { next: *mut Node }
+/// # impl Linked for Node { fn next(&self) -> *mut Node { self.next } }
+/// # impl Alloc for Node { fn free(_: *mut Self) {} }
+/// let a = Box::into_raw(Box::new(Node { next: std::ptr::null_mut() }));
+/// let b = Box::into_raw(Box::new(Node { next: a }));
+/// unsafe { (*a).next = b };
+/// let list = unsafe { LinkedList::new(a) };
+/// list.iter().count();
+/// ```
+pub struct LinkedList<T: Alloc + Linked>(Option<Ptr<T>>);
+
+impl<T: Alloc + Linked> LinkedList<T> {
+    /// Take ownership of a list through its head, which may be null.
+    ///
+    /// # Safety
+    ///
+    /// `head` must be either null or a valid pointer to `T`, which can be
+    /// freed, along with all nodes reachable from it, with `T`'s [`Alloc`]
+    /// implementation, and must not be owned by anything else.
+    pub unsafe fn new(head: *mut T) -> LinkedList<T> {
+        LinkedList(Ptr::new(head))
+    }
+
+    pub fn into_raw(list: LinkedList<T>) -> *mut T {
+        list.0.map_or(ptr::null_mut(), Ptr::into_raw)
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.0.as_deref()
+    }
+
+    pub fn iter(&self) -> LinkedListIter<'_, T> {
+        let head = self.0.as_ref().map_or(ptr::null(), Ptr::as_ptr);
+        LinkedListIter {
+            next: head,
+            slow: head,
+            advance_slow: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Number of nodes.
+    ///
+    /// This has to walk the whole list.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+impl<T: Alloc + Linked> From<Ptr<T>> for LinkedList<T> {
+    fn from(head: Ptr<T>) -> LinkedList<T> {
+        LinkedList(Some(head))
+    }
+}
+
+impl<'a, T: Alloc + Linked> IntoIterator for &'a LinkedList<T> {
+    type Item = &'a T;
+    type IntoIter = LinkedListIter<'a, T>;
+
+    fn into_iter(self) -> LinkedListIter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Iterator over the nodes of a [`LinkedList`].
+pub struct LinkedListIter<'a, T: Linked> {
+    next: *const T,
+    /// Second cursor advancing at half the speed of `next`, used to detect
+    /// cycles.
+    slow: *const T,
+    advance_slow: bool,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: Linked> Iterator for LinkedListIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = unsafe { self.next.as_ref()? };
+        self.next = node.next();
+
+        if self.advance_slow {
+            self.slow = unsafe { (*self.slow).next() };
+        }
+        self.advance_slow = !self.advance_slow;
+        assert!(
+            self.next.is_null() || self.next != self.slow,
+            "cycle detected in a linked list"
+        );
+
+        Some(node)
+    }
+}
+
+impl<T: Linked> FusedIterator for LinkedListIter<'_, T> {}
@@ -0,0 +1,370 @@
+use std::{ffi::CStr, iter::FusedIterator, marker::PhantomData, os::raw::c_char, ptr::NonNull};
+
+use crate::{Deleter, LibcFree, Movable, NullPointer, Ref, ThreadSafe};
+
+/// Element of a null-terminated array of pointers.
+///
+/// This determines what iterating over a `T**` yields: [`Ref`]s for arbitrary
+/// `T`, and [`CStr`]s for `char**`, which is represented as an array of
+/// `CStr`.
+pub trait Element {
+    /// Type the array's pointers point to.
+    type Raw;
+
+    /// Borrowed form of an element.
+    type Item<'a>
+    where
+        Self: 'a;
+
+    /// # Safety
+    ///
+    /// `raw` must be a valid pointer to `Self::Raw`, which remains valid for
+    /// the whole lifetime `'a`.
+    unsafe fn item<'a>(raw: NonNull<Self::Raw>) -> Self::Item<'a>;
+}
+
+impl<T> Element for T {
+    type Raw = T;
+    type Item<'a>
+        = Ref<'a, T>
+    where
+        T: 'a;
+
+    unsafe fn item<'a>(raw: NonNull<T>) -> Ref<'a, T> {
+        Ref::from_raw(raw.as_ptr())
+    }
+}
+
+impl Element for CStr {
+    type Raw = c_char;
+    type Item<'a> = &'a CStr;
+
+    unsafe fn item<'a>(raw: NonNull<c_char>) -> &'a CStr {
+        CStr::from_ptr(raw.as_ptr())
+    }
+}
+
+/// Borrowed null-terminated array of pointers.
+///
+/// This is a view of an `environ`-style `T**` array, whose end is marked with
+/// a null pointer, and which remains owned by some parent object.
+///
+/// ```
+/// use std::{ffi::CStr, os::raw::c_char, ptr};
+/// use cffi::NullTerminated;
+///
+/// let names = [
+///     b"foo\0".as_ptr() as *mut c_char,
+///     b"bar\0".as_ptr() as *mut c_char,
+///     ptr::null_mut(),
+/// ];
+/// let names = unsafe { NullTerminated::<CStr>::new(&names, names.as_ptr()) }.unwrap();
+/// assert_eq!(names.len(), 2);
+/// assert_eq!(names.iter().map(CStr::to_bytes).collect::<Vec<_>>(), [b"foo", b"bar"]);
+/// ```
+///
+/// Arrays of any other type yield [`Ref`]s:
+///
+/// ```
+/// use cffi::NullTerminated;
+///
+/// let (mut a, mut b) = (1, 2);
+/// let items = [&mut a as *mut i32, &mut b, std::ptr::null_mut()];
+/// let items = unsafe { NullTerminated::<i32>::new(&items, items.as_ptr()) }.unwrap();
+/// assert_eq!(items.into_iter().map(|item| *item).sum::<i32>(), 3);
+/// ```
+pub struct NullTerminated<'a, T: ?Sized + Element> {
+    raw: NonNull<*mut T::Raw>,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: ?Sized + Element> NullTerminated<'a, T> {
+    /// Borrow an array owned by `parent`, returning `None` if it is null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to a null-terminated array
+    /// of valid pointers, which remain valid, and are not mutated, for as long
+    /// as `parent` is borrowed.
+    pub unsafe fn new<P: ?Sized>(
+        _parent: &'a P,
+        raw: *const *mut T::Raw,
+    ) -> Option<NullTerminated<'a, T>> {
+        NonNull::new(raw as *mut _).map(|raw| NullTerminated {
+            raw,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Borrow an array owned by `parent`, failing with [`NullPointer`] if it
+    /// is null.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`NullTerminated::new`].
+    pub unsafe fn try_from_raw<P: ?Sized>(
+        parent: &'a P,
+        raw: *const *mut T::Raw,
+    ) -> Result<NullTerminated<'a, T>, NullPointer> {
+        NullTerminated::new(parent, raw).ok_or(NullPointer)
+    }
+
+    /// Borrow an array for an arbitrary lifetime.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid, non-null pointer to a null-terminated array of
+    /// valid pointers, which remain valid, and are not mutated, for the whole
+    /// lifetime `'a`.
+    pub unsafe fn from_raw(raw: *const *mut T::Raw) -> NullTerminated<'a, T> {
+        debug_assert!(
+            !raw.is_null(),
+            "NullTerminated::from_raw called with a null pointer"
+        );
+        NullTerminated {
+            raw: NonNull::new_unchecked(raw as *mut _),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *const *mut T::Raw {
+        self.raw.as_ptr()
+    }
+
+    pub fn iter(&self) -> NullTerminatedIter<'a, T> {
+        NullTerminatedIter {
+            next: self.raw,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Number of elements, not counting the terminator.
+    ///
+    /// This has to walk the whole array.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        unsafe { (*self.raw.as_ptr()).is_null() }
+    }
+}
+
+impl<T: ?Sized + Element> Clone for NullTerminated<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized + Element> Copy for NullTerminated<'_, T> {}
+
+impl<'a, T: ?Sized + Element> IntoIterator for NullTerminated<'a, T> {
+    type Item = T::Item<'a>;
+    type IntoIter = NullTerminatedIter<'a, T>;
+
+    fn into_iter(self) -> NullTerminatedIter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Iterator over a null-terminated array of pointers.
+pub struct NullTerminatedIter<'a, T: ?Sized + Element> {
+    next: NonNull<*mut T::Raw>,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: ?Sized + Element> Iterator for NullTerminatedIter<'a, T> {
+    type Item = T::Item<'a>;
+
+    fn next(&mut self) -> Option<T::Item<'a>> {
+        let item = NonNull::new(unsafe { *self.next.as_ptr() })?;
+        self.next = unsafe { NonNull::new_unchecked(self.next.as_ptr().add(1)) };
+        Some(unsafe { T::item(item) })
+    }
+}
+
+impl<T: ?Sized + Element> FusedIterator for NullTerminatedIter<'_, T> {}
+
+/// Owned null-terminated array of pointers.
+///
+/// This is the owning counterpart of [`NullTerminated`]. When dropped, each
+/// element is freed with a deleter `E`, and then the array itself with a
+/// deleter `D`, both of which default to libc's `free`.
+///
+/// ```
+/// use std::{ffi::CStr, os::raw::c_char};
+/// use cffi::OwnedNullTerminated;
+///
+/// let raw = unsafe {
+///     let raw = libc::calloc(2, std::mem::size_of::<*mut c_char>()) as *mut *mut c_char;
+///     *raw = libc::strdup(b"foo\0".as_ptr() as *const c_char);
+///     raw
+/// };
+/// let names: OwnedNullTerminated<CStr> = unsafe { OwnedNullTerminated::new(raw) }.unwrap();
+/// assert_eq!(names.iter().next().unwrap().to_bytes(), b"foo");
+///
+/// fn assert_thread_safe<T: Send + Sync>() {}
+/// assert_thread_safe::<OwnedNullTerminated<CStr>>();
+/// ```
+///
+/// Like [`PtrWith`], it is [`Send`] if its elements are [`Movable`], and
+/// [`Sync`] if they are [`ThreadSafe`]:
+///
+/// ```
+/// use cffi::{Alloc, AllocDeleter, Movable, OwnedNullTerminated, Private, Ref, ThreadSafe};
+///
+/// struct Foo(Private);
+///
+/// impl Alloc for Foo {
+///     fn free(_: *mut Self) {}
+/// }
+///
+/// unsafe impl Movable for Foo {}
+/// unsafe impl ThreadSafe for Foo {}
+///
+/// fn assert_thread_safe<T: Send + Sync>() {}
+/// assert_thread_safe::<OwnedNullTerminated<Foo, AllocDeleter>>();
+/// assert_thread_safe::<Ref<Foo>>();
+/// ```
+///
+/// [`PtrWith`]: crate::PtrWith
+pub struct OwnedNullTerminated<T, E = LibcFree, D = LibcFree>
+where
+    T: ?Sized + Element,
+    E: Deleter<T::Raw>,
+    D: Deleter<*mut T::Raw>,
+{
+    raw: NonNull<*mut T::Raw>,
+    element_deleter: E,
+    deleter: D,
+}
+
+unsafe impl<T, E, D> Send for OwnedNullTerminated<T, E, D>
+where
+    T: ?Sized + Element + Movable,
+    E: Deleter<T::Raw> + Send,
+    D: Deleter<*mut T::Raw> + Send,
+{
+}
+
+unsafe impl<T, E, D> Sync for OwnedNullTerminated<T, E, D>
+where
+    T: ?Sized + Element + ThreadSafe,
+    E: Deleter<T::Raw> + Sync,
+    D: Deleter<*mut T::Raw> + Sync,
+{
+}
+
+impl<T, E, D> OwnedNullTerminated<T, E, D>
+where
+    T: ?Sized + Element,
+    E: Deleter<T::Raw> + Default,
+    D: Deleter<*mut T::Raw> + Default,
+{
+    /// Take ownership of an array, returning `None` if it is null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to a null-terminated array
+    /// of valid pointers. The elements must be freeable with `E` and the array
+    /// with `D`, and neither may be owned by anything else.
+    pub unsafe fn new(raw: *mut *mut T::Raw) -> Option<OwnedNullTerminated<T, E, D>> {
+        OwnedNullTerminated::new_with(raw, E::default(), D::default())
+    }
+
+    /// Take ownership of an array, failing with [`NullPointer`] if it is null.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`OwnedNullTerminated::new`].
+    pub unsafe fn try_from_raw(
+        raw: *mut *mut T::Raw,
+    ) -> Result<OwnedNullTerminated<T, E, D>, NullPointer> {
+        OwnedNullTerminated::new(raw).ok_or(NullPointer)
+    }
+}
+
+impl<T, E, D> OwnedNullTerminated<T, E, D>
+where
+    T: ?Sized + Element,
+    E: Deleter<T::Raw>,
+    D: Deleter<*mut T::Raw>,
+{
+    /// Take ownership of an array whose elements are freed with
+    /// `element_deleter` and which itself is freed with `deleter`, returning
+    /// `None` if it is null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to a null-terminated array
+    /// of valid pointers. The elements must be freeable with `element_deleter`
+    /// and the array with `deleter`, and neither may be owned by anything
+    /// else.
+    pub unsafe fn new_with(
+        raw: *mut *mut T::Raw,
+        element_deleter: E,
+        deleter: D,
+    ) -> Option<OwnedNullTerminated<T, E, D>> {
+        NonNull::new(raw).map(|raw| OwnedNullTerminated {
+            raw,
+            element_deleter,
+            deleter,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *const *mut T::Raw {
+        self.raw.as_ptr()
+    }
+
+    /// Borrow the array.
+    pub fn as_view(&self) -> NullTerminated<'_, T> {
+        unsafe { NullTerminated::from_raw(self.raw.as_ptr()) }
+    }
+
+    pub fn iter(&self) -> NullTerminatedIter<'_, T> {
+        self.as_view().iter()
+    }
+
+    /// Number of elements, not counting the terminator.
+    ///
+    /// This has to walk the whole array.
+    pub fn len(&self) -> usize {
+        self.as_view().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_view().is_empty()
+    }
+}
+
+impl<'a, T, E, D> IntoIterator for &'a OwnedNullTerminated<T, E, D>
+where
+    T: ?Sized + Element,
+    E: Deleter<T::Raw>,
+    D: Deleter<*mut T::Raw>,
+{
+    type Item = T::Item<'a>;
+    type IntoIter = NullTerminatedIter<'a, T>;
+
+    fn into_iter(self) -> NullTerminatedIter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T, E, D> Drop for OwnedNullTerminated<T, E, D>
+where
+    T: ?Sized + Element,
+    E: Deleter<T::Raw>,
+    D: Deleter<*mut T::Raw>,
+{
+    fn drop(&mut self) {
+        let mut next = self.raw.as_ptr();
+        unsafe {
+            while !(*next).is_null() {
+                self.element_deleter.delete(*next);
+                next = next.add(1);
+            }
+        }
+        self.deleter.delete(self.raw.as_ptr());
+    }
+}
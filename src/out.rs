@@ -0,0 +1,296 @@
+//! Helpers for calling C functions which return values through
+//! out-parameters.
+//!
+//! Functions such as `int foo_create(foo_t **out)` or
+//! `int foo_stat(foo_t*, struct stat *out)` write their results through
+//! pointers passed by the caller. The helpers in this module provide those
+//! pointers, check the function's status with an [`ErrorCode`] convention,
+//! and only assume the results were initialized if it succeeded.
+//!
+//! ```
+//! use std::os::raw::c_int;
+//! use cffi::{error::ZeroIsSuccess, out};
+//!
+//! unsafe extern "C" fn divide(a: c_int, b: c_int, quot: *mut c_int, rem: *mut c_int) -> c_int {
+//!     if b == 0 {
+//!         return 1;
+//!     }
+//!     *quot = a / b;
+//!     *rem = a % b;
+//!     0
+//! }
+//!
+//! let quot = unsafe { out::value(ZeroIsSuccess, |out| divide(7, 2, out, &mut 0)) };
+//! assert_eq!(quot, Ok(3));
+//!
+//! let result = unsafe {
+//!     out::call::<(out::Value<c_int>, out::Value<c_int>), _, _, _>(ZeroIsSuccess, |(quot, rem)| {
+//!         divide(7, 2, quot, rem)
+//!     })
+//! };
+//! assert_eq!(result, Ok((3, 1)));
+//!
+//! let result = unsafe { out::value(ZeroIsSuccess, |out| divide(7, 0, out, &mut 0)) };
+//! assert!(result.is_err());
+//! ```
+//!
+//! [`ErrorCode`]: crate::error::ErrorCode
+
+use std::{marker::PhantomData, mem::MaybeUninit, ptr};
+
+use crate::{error::ErrorCode, Alloc, Ptr};
+
+/// Kind of out-parameter.
+///
+/// This is implemented for [`Value`], [`Owned`], [`Nullable`], and tuples of
+/// up to four out-parameters.
+pub trait OutParam {
+    /// Storage for the out-parameter.
+    type Slot;
+    /// Pointer passed to the C function.
+    type Pointer;
+    /// Value produced after a successful call.
+    type Output;
+
+    fn slot() -> Self::Slot;
+
+    fn pointer(slot: &mut Self::Slot) -> Self::Pointer;
+
+    /// # Safety
+    ///
+    /// The C function must have initialized the slot.
+    unsafe fn assume_init(slot: Self::Slot) -> Self::Output;
+}
+
+/// Out-parameter of type `*mut T`, producing a `T`.
+pub struct Value<T>(PhantomData<T>);
+
+/// Out-parameter of type `*mut *mut T`, producing an owned [`Ptr<T>`].
+///
+/// A call which succeeds but leaves the pointer null causes a panic. Use
+/// [`Nullable`] for functions which may legitimately do so.
+pub struct Owned<T>(PhantomData<T>);
+
+/// Out-parameter of type `*mut *mut T`, producing an owned [`Ptr<T>`] if the
+/// pointer is non-null.
+///
+/// This is for lookup functions such as
+/// `int foo_find(const char*, foo_t **out)`, which succeed but leave the
+/// pointer null when nothing was found.
+pub struct Nullable<T>(PhantomData<T>);
+
+impl<T> OutParam for Value<T> {
+    type Slot = MaybeUninit<T>;
+    type Pointer = *mut T;
+    type Output = T;
+
+    fn slot() -> MaybeUninit<T> {
+        MaybeUninit::uninit()
+    }
+
+    fn pointer(slot: &mut MaybeUninit<T>) -> *mut T {
+        slot.as_mut_ptr()
+    }
+
+    unsafe fn assume_init(slot: MaybeUninit<T>) -> T {
+        slot.assume_init()
+    }
+}
+
+impl<T: Alloc> OutParam for Owned<T> {
+    type Slot = *mut T;
+    type Pointer = *mut *mut T;
+    type Output = Ptr<T>;
+
+    fn slot() -> *mut T {
+        ptr::null_mut()
+    }
+
+    fn pointer(slot: &mut *mut T) -> *mut *mut T {
+        slot
+    }
+
+    unsafe fn assume_init(slot: *mut T) -> Ptr<T> {
+        Ptr::new(slot).expect("function reported success but stored a null pointer")
+    }
+}
+
+impl<T: Alloc> OutParam for Nullable<T> {
+    type Slot = *mut T;
+    type Pointer = *mut *mut T;
+    type Output = Option<Ptr<T>>;
+
+    fn slot() -> *mut T {
+        ptr::null_mut()
+    }
+
+    fn pointer(slot: &mut *mut T) -> *mut *mut T {
+        slot
+    }
+
+    unsafe fn assume_init(slot: *mut T) -> Option<Ptr<T>> {
+        Ptr::new(slot)
+    }
+}
+
+macro_rules! tuple_out_params {
+    ($($name:ident: $index:tt),*) => {
+        impl<$($name: OutParam),*> OutParam for ($($name,)*) {
+            type Slot = ($($name::Slot,)*);
+            type Pointer = ($($name::Pointer,)*);
+            type Output = ($($name::Output,)*);
+
+            fn slot() -> Self::Slot {
+                ($($name::slot(),)*)
+            }
+
+            fn pointer(slot: &mut Self::Slot) -> Self::Pointer {
+                ($($name::pointer(&mut slot.$index),)*)
+            }
+
+            unsafe fn assume_init(slot: Self::Slot) -> Self::Output {
+                ($($name::assume_init(slot.$index),)*)
+            }
+        }
+    };
+}
+
+tuple_out_params!(A: 0, B: 1);
+tuple_out_params!(A: 0, B: 1, C: 2);
+tuple_out_params!(A: 0, B: 1, C: 2, D: 3);
+
+/// Call `function` with pointers to out-parameters `O`, and produce their
+/// values if it succeeds according to `convention`.
+///
+/// # Safety
+///
+/// If `function` succeeds, it must have initialized all out-parameters.
+pub unsafe fn call<O, S, C, F>(convention: C, function: F) -> Result<O::Output, C::Error>
+where
+    O: OutParam,
+    C: ErrorCode<S>,
+    F: FnOnce(O::Pointer) -> S,
+{
+    let mut slot = O::slot();
+    let status = function(O::pointer(&mut slot));
+    if convention.is_success(&status) {
+        Ok(O::assume_init(slot))
+    } else {
+        Err(convention.to_error(status))
+    }
+}
+
+/// Call `function` with a `*mut T` out-parameter, and produce its value if it
+/// succeeds according to `convention`.
+///
+/// # Safety
+///
+/// If `function` succeeds, it must have initialized the out-parameter.
+pub unsafe fn value<T, S, C, F>(convention: C, function: F) -> Result<T, C::Error>
+where
+    C: ErrorCode<S>,
+    F: FnOnce(*mut T) -> S,
+{
+    call::<Value<T>, S, C, F>(convention, function)
+}
+
+/// Call `function` with a `*mut *mut T` out-parameter, and take ownership of
+/// the stored pointer if it succeeds according to `convention`.
+///
+/// ```
+/// use std::os::raw::c_int;
+/// use cffi::{error::ZeroIsSuccess, out, Alloc};
+///
+/// struct Foo(c_int);
+///
+/// impl Alloc for Foo {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// unsafe extern "C" fn foo_create(value: c_int, out: *mut *mut Foo) -> c_int {
+///     if value < 0 {
+///         return 1;
+///     }
+///     *out = Box::into_raw(Box::new(Foo(value)));
+///     0
+/// }
+///
+/// let foo = unsafe { out::ptr(ZeroIsSuccess, |out| foo_create(5, out)) };
+/// assert_eq!(foo.ok().unwrap().0, 5);
+///
+/// let foo = unsafe { out::ptr(ZeroIsSuccess, |out| foo_create(-1, out)) };
+/// assert!(foo.is_err());
+/// ```
+///
+/// # Safety
+///
+/// If `function` succeeds, it must have stored a pointer which can be owned
+/// by a [`Ptr`].
+///
+/// # Panics
+///
+/// Panics if `function` succeeds but leaves the pointer null. Use
+/// [`optional_ptr`] for functions which may legitimately do so.
+pub unsafe fn ptr<T, S, C, F>(convention: C, function: F) -> Result<Ptr<T>, C::Error>
+where
+    T: Alloc,
+    C: ErrorCode<S>,
+    F: FnOnce(*mut *mut T) -> S,
+{
+    call::<Owned<T>, S, C, F>(convention, function)
+}
+
+/// Call `function` with a `*mut *mut T` out-parameter, and take ownership of
+/// the stored pointer, if any, if it succeeds according to `convention`.
+///
+/// ```
+/// use std::os::raw::c_int;
+/// use cffi::{error::ZeroIsSuccess, out, Alloc};
+///
+/// struct Foo(c_int);
+///
+/// impl Alloc for Foo {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// unsafe extern "C" fn foo_find(value: c_int, out: *mut *mut Foo) -> c_int {
+///     if value < 0 {
+///         return 1;
+///     }
+///     *out = if value == 0 {
+///         std::ptr::null_mut()
+///     } else {
+///         Box::into_raw(Box::new(Foo(value)))
+///     };
+///     0
+/// }
+///
+/// let found = unsafe { out::optional_ptr(ZeroIsSuccess, |out| foo_find(5, out)) };
+/// assert_eq!(found.ok().unwrap().unwrap().0, 5);
+///
+/// let found = unsafe { out::optional_ptr(ZeroIsSuccess, |out| foo_find(0, out)) };
+/// assert!(found.ok().unwrap().is_none());
+///
+/// let found = unsafe { out::optional_ptr(ZeroIsSuccess, |out| foo_find(-1, out)) };
+/// assert!(found.is_err());
+/// ```
+///
+/// # Safety
+///
+/// If `function` succeeds, it must have stored either null or a pointer which
+/// can be owned by a [`Ptr`].
+pub unsafe fn optional_ptr<T, S, C, F>(
+    convention: C,
+    function: F,
+) -> Result<Option<Ptr<T>>, C::Error>
+where
+    T: Alloc,
+    C: ErrorCode<S>,
+    F: FnOnce(*mut *mut T) -> S,
+{
+    call::<Nullable<T>, S, C, F>(convention, function)
+}
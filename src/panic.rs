@@ -0,0 +1,101 @@
+use std::{
+    any::Any,
+    cell::RefCell,
+    panic::{self, AssertUnwindSafe},
+    process,
+};
+
+thread_local! {
+    static PENDING_PANIC: RefCell<Option<Box<dyn Any + Send>>> = RefCell::new(None);
+}
+
+/// Value returned to C in place of a result when a callback panics.
+///
+/// Any [`Clone`] value is its own sentinel, while [`Abort`] aborts the process
+/// instead of returning.
+pub trait Sentinel<R> {
+    fn sentinel(&self) -> R;
+}
+
+impl<R: Clone> Sentinel<R> for R {
+    fn sentinel(&self) -> R {
+        self.clone()
+    }
+}
+
+/// Sentinel which aborts the process instead of returning to C.
+#[derive(Debug, Default)]
+pub struct Abort;
+
+impl<R> Sentinel<R> for Abort {
+    fn sentinel(&self) -> R {
+        process::abort()
+    }
+}
+
+/// Call a function, preventing panics from unwinding out of it.
+///
+/// This is meant to wrap bodies of `extern "C"` functions called from C, which
+/// must not unwind. If `function` panics, its payload is stored until it can
+/// be re-raised with [`resume_pending_panic`] once control returns to Rust,
+/// and `sentinel` is returned instead. If a panic is already pending, the new
+/// one is discarded.
+///
+/// ```
+/// use std::os::raw::c_int;
+///
+/// extern "C" fn callback(value: c_int) -> c_int {
+///     cffi::catch_unwind_ffi(-1, || {
+///         assert!(value >= 0, "negative value");
+///         value * 2
+///     })
+/// }
+///
+/// assert_eq!(callback(2), 4);
+/// assert_eq!(callback(-2), -1);
+///
+/// let panic = std::panic::catch_unwind(cffi::resume_pending_panic).unwrap_err();
+/// assert_eq!(panic.downcast_ref::<&str>(), Some(&"negative value"));
+/// ```
+pub fn catch_unwind_ffi<R, S, F>(sentinel: S, function: F) -> R
+where
+    S: Sentinel<R>,
+    F: FnOnce() -> R,
+{
+    catch_unwind_with(&sentinel, function)
+}
+
+pub(crate) fn catch_unwind_with<R, S, F>(sentinel: &S, function: F) -> R
+where
+    S: Sentinel<R> + ?Sized,
+    F: FnOnce() -> R,
+{
+    match panic::catch_unwind(AssertUnwindSafe(function)) {
+        Ok(result) => result,
+        Err(payload) => {
+            PENDING_PANIC.with(|pending| {
+                let mut pending = pending.borrow_mut();
+                if pending.is_none() {
+                    *pending = Some(payload);
+                }
+            });
+            sentinel.sentinel()
+        }
+    }
+}
+
+/// Re-raise a panic caught by [`catch_unwind_ffi`] on this thread, if any.
+///
+/// This should be called after each call to a C function which may have
+/// invoked callbacks.
+pub fn resume_pending_panic() {
+    if let Some(payload) = take_pending_panic() {
+        panic::resume_unwind(payload);
+    }
+}
+
+/// Take the payload of a panic caught by [`catch_unwind_ffi`] on this thread,
+/// if any, without re-raising it.
+pub fn take_pending_panic() -> Option<Box<dyn Any + Send>> {
+    PENDING_PANIC.with(|pending| pending.borrow_mut().take())
+}
@@ -0,0 +1,126 @@
+use crate::{Alloc, Ptr};
+
+/// Callback registered with a C object.
+///
+/// Registering a callback with a C library (`foo_set_handler(foo, cb, ud)` or
+/// `id = foo_add_listener(foo, cb, ud)`) requires the callback to stay alive
+/// until it is unregistered again. A `Registration` owns the callback, usually
+/// a [`Callback`] or one of its variants, and calls a user-supplied function
+/// to unregister it when dropped, before the callback itself is freed. It
+/// borrows the object it was registered with, so the object can't be freed
+/// while the registration is active.
+///
+/// ```
+/// use std::{cell::Cell, os::raw::{c_int, c_void}, ptr};
+/// use cffi::{Alloc, Callback, Ptr, Registration, Trampoline};
+///
+/// type Handler = unsafe extern "C" fn(*mut c_void, c_int);
+///
+/// #[derive(Default)]
+/// struct Foo {
+///     handler: Cell<Option<(Handler, *mut c_void)>>,
+/// }
+///
+/// impl Alloc for Foo {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// unsafe extern "C" fn foo_set_handler(foo: *mut Foo, cb: Option<Handler>, ud: *mut c_void) {
+///     (*foo).handler.set(cb.map(|cb| (cb, ud)));
+/// }
+///
+/// unsafe extern "C" fn foo_fire(foo: *mut Foo, ev: c_int) {
+///     if let Some((cb, ud)) = (*foo).handler.get() {
+///         cb(ud, ev);
+///     }
+/// }
+///
+/// let foo = unsafe { Ptr::from_raw(Box::into_raw(Box::<Foo>::default())) };
+/// let raw = Ptr::as_ptr(&foo) as *mut Foo;
+/// let events = Cell::new(0);
+///
+/// let registration = Registration::new(
+///     &foo,
+///     Callback::new(|_: c_int| events.set(events.get() + 1)),
+///     |foo, cb| unsafe { foo_set_handler(foo, Some(cb.trampoline()), cb.user_data()) },
+///     |foo, ()| unsafe { foo_set_handler(foo, None, ptr::null_mut()) },
+/// );
+/// unsafe { foo_fire(raw, 1) };
+/// drop(registration);
+/// unsafe { foo_fire(raw, 2) };
+/// assert_eq!(events.get(), 1);
+/// ```
+///
+/// [`Callback`]: crate::Callback
+pub struct Registration<'a, T: Alloc, C> {
+    owner: &'a Ptr<T>,
+    callback: Box<C>,
+    unregister: Option<Box<dyn FnOnce(*mut T) + 'a>>,
+}
+
+impl<'a, T: Alloc, C> Registration<'a, T, C> {
+    /// Register `callback` with `owner`.
+    ///
+    /// `register` is called immediately, and its result (e.g. a listener ID)
+    /// is passed to `unregister` when this registration is dropped.
+    pub fn new<K, R, U>(owner: &'a Ptr<T>, callback: C, register: R, unregister: U) -> Self
+    where
+        R: FnOnce(*mut T, &C) -> K,
+        U: FnOnce(*mut T, K) + 'a,
+        K: 'a,
+    {
+        let callback = Box::new(callback);
+        let key = register(Ptr::as_ptr(owner) as *mut T, &callback);
+        Registration::registered(owner, callback, key, unregister)
+    }
+
+    /// Register `callback` with `owner`, failing if `register` does.
+    ///
+    /// If registration fails the callback is freed immediately and
+    /// `unregister` is never called.
+    pub fn try_new<K, E, R, U>(
+        owner: &'a Ptr<T>,
+        callback: C,
+        register: R,
+        unregister: U,
+    ) -> Result<Self, E>
+    where
+        R: FnOnce(*mut T, &C) -> Result<K, E>,
+        U: FnOnce(*mut T, K) + 'a,
+        K: 'a,
+    {
+        let callback = Box::new(callback);
+        let key = register(Ptr::as_ptr(owner) as *mut T, &callback)?;
+        Ok(Registration::registered(owner, callback, key, unregister))
+    }
+
+    fn registered<K, U>(owner: &'a Ptr<T>, callback: Box<C>, key: K, unregister: U) -> Self
+    where
+        U: FnOnce(*mut T, K) + 'a,
+        K: 'a,
+    {
+        Registration {
+            owner,
+            callback,
+            unregister: Some(Box::new(move |owner| unregister(owner, key))),
+        }
+    }
+
+    pub fn owner(&self) -> &'a Ptr<T> {
+        self.owner
+    }
+
+    pub fn callback(&self) -> &C {
+        &self.callback
+    }
+}
+
+impl<T: Alloc, C> Drop for Registration<'_, T, C> {
+    fn drop(&mut self) {
+        if let Some(unregister) = self.unregister.take() {
+            unregister(Ptr::as_ptr(self.owner) as *mut T);
+        }
+    }
+}
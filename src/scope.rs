@@ -0,0 +1,205 @@
+use std::{cell::RefCell, fmt, os::raw::c_void, ptr};
+
+use crate::{Alloc, Deleter, Ptr};
+
+/// Arena of owned pointers, freed together.
+///
+/// Acquiring many C resources, and releasing all of them on every return
+/// path, is tedious with individual [`Ptr`]s. A `Scope` takes ownership of
+/// any number of raw pointers, hands back mutable borrows of them which live
+/// as long as the scope, and frees them all in reverse order of acquisition
+/// when dropped, much like a chain of `goto cleanup` labels in C.
+///
+/// ```
+/// use std::cell::RefCell;
+/// use cffi::{Alloc, Scope};
+///
+/// thread_local!(static FREED: RefCell<Vec<u32>> = RefCell::new(Vec::new()));
+///
+/// struct Handle(u32);
+///
+/// impl Alloc for Handle {
+///     fn free(this: *mut Self) {
+///         let handle = unsafe { Box::from_raw(this) };
+///         FREED.with(|freed| freed.borrow_mut().push(handle.0));
+///     }
+/// }
+///
+/// fn new_handle(id: u32) -> *mut Handle {
+///     Box::into_raw(Box::new(Handle(id)))
+/// }
+///
+/// {
+///     let scope = Scope::new();
+///     let a = unsafe { scope.add(new_handle(1)) }.unwrap();
+///     let b = unsafe { scope.add(new_handle(2)) }.unwrap();
+///     let c = unsafe { scope.add(new_handle(3)) }.unwrap();
+///     b.0 *= 10;
+///     assert_eq!(a.0, 1);
+///
+///     let c = unsafe { scope.release(c) };
+///     assert_eq!(c.0, 3);
+/// }
+///
+/// FREED.with(|freed| assert_eq!(*freed.borrow(), [3, 20, 1]));
+/// ```
+///
+/// Releasing an item as a different type panics:
+///
+/// ```should_panic
+/// use cffi::{Alloc, Scope};
+///
+/// struct Handle(u32);
+/// struct Other(u32);
+///
+/// impl Alloc for Handle {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// impl Alloc for Other {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// let scope = Scope::new();
+/// let handle = unsafe { scope.add(Box::into_raw(Box::new(Handle(1)))) }.unwrap();
+/// let other = unsafe { &mut *(handle as *mut Handle as *mut Other) };
+/// let _other = unsafe { scope.release(other) };
+/// ```
+pub struct Scope<'a> {
+    entries: RefCell<Vec<Entry<'a>>>,
+}
+
+struct Entry<'a> {
+    raw: *mut c_void,
+    free: Free<'a>,
+}
+
+enum Free<'a> {
+    Alloc(fn(*mut c_void)),
+    Deleter(Box<dyn FnMut(*mut c_void) + 'a>),
+}
+
+impl<'a> Scope<'a> {
+    pub fn new() -> Scope<'a> {
+        Scope {
+            entries: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Take ownership of a raw pointer, returning `None` if it is null.
+    ///
+    /// The pointer will be freed with `T`'s [`Alloc`] implementation when
+    /// this scope is dropped, unless it is [released](Scope::release) first.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`Ptr::new`].
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn add<T: Alloc>(&self, raw: *mut T) -> Option<&mut T> {
+        self.push(raw, Free::Alloc(free::<T>))
+    }
+
+    /// Take ownership of a raw pointer to be freed with `deleter`, returning
+    /// `None` if it is null.
+    ///
+    /// If the pointer is null `deleter` is dropped without being called.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to `T` which can be freed
+    /// with `deleter`, and must not be owned by anything else.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn add_with<T, D>(&self, raw: *mut T, mut deleter: D) -> Option<&mut T>
+    where
+        D: Deleter<T> + 'a,
+    {
+        self.push(
+            raw,
+            Free::Deleter(Box::new(move |raw| deleter.delete(raw as *mut T))),
+        )
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn push<T>(&self, raw: *mut T, free: Free<'a>) -> Option<&mut T> {
+        if raw.is_null() {
+            return None;
+        }
+
+        self.entries.borrow_mut().push(Entry {
+            raw: raw as *mut c_void,
+            free,
+        });
+        Some(&mut *raw)
+    }
+
+    /// Remove an item from this scope, returning it as a standalone [`Ptr`].
+    ///
+    /// # Safety
+    ///
+    /// `item` must not be used afterwards. This can't be enforced by the
+    /// borrow checker, since mutable references are implicitly reborrowed
+    /// when passed to a function.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `item` was not added to this scope with [`Scope::add`] as a
+    /// `T`.
+    pub unsafe fn release<T: Alloc>(&self, item: &mut T) -> Ptr<T> {
+        let raw = item as *mut T;
+        let mut entries = self.entries.borrow_mut();
+        let index = entries
+            .iter()
+            .rposition(|entry| entry.raw == raw as *mut c_void)
+            .expect("item does not belong to this scope");
+        assert!(
+            matches!(
+                entries[index].free,
+                Free::Alloc(f) if ptr::fn_addr_eq(f, free::<T> as fn(*mut c_void))
+            ),
+            "only items added with Scope::add as the same type can be released"
+        );
+        entries.remove(index);
+        Ptr::from_raw(raw)
+    }
+
+    /// Number of items owned by this scope.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+}
+
+fn free<T: Alloc>(raw: *mut c_void) {
+    Alloc::free(raw as *mut T)
+}
+
+impl Default for Scope<'_> {
+    fn default() -> Self {
+        Scope::new()
+    }
+}
+
+impl fmt::Debug for Scope<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Scope").field("len", &self.len()).finish()
+    }
+}
+
+impl Drop for Scope<'_> {
+    fn drop(&mut self) {
+        let entries = self.entries.get_mut();
+        while let Some(entry) = entries.pop() {
+            match entry.free {
+                Free::Alloc(free) => free(entry.raw),
+                Free::Deleter(mut deleter) => deleter(entry.raw),
+            }
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use crate::{Alloc, Movable, Ptr};
+
+thread_local!(static THREAD: u8 = const { 0 });
+
+/// Identifier of the current thread, never zero.
+fn current_thread() -> usize {
+    THREAD.with(|thread| thread as *const u8 as usize)
+}
+
+/// Process-wide lock serializing all calls into a non-reentrant library.
+///
+/// Some C libraries keep global state without synchronising access to it, so
+/// no two calls into them may ever run at the same time, even on unrelated
+/// objects. A `Serialized` lock is declared once per such library, usually as
+/// a `static` tagged with a marker type `L` naming the library, and all calls
+/// into the library are made while holding it, either directly through
+/// [`Serialized::lock`], or through handles wrapped in [`Locked`].
+///
+/// The lock is not reentrant. Attempting to take it on a thread which already
+/// holds it, for example from a callback invoked by the library, panics
+/// instead of deadlocking.
+///
+/// ```
+/// use std::{cell::Cell, thread};
+/// use cffi::{Alloc, Locked, Movable, Ptr, Serialized};
+///
+/// struct Counter(Cell<u32>);
+///
+/// impl Alloc for Counter {
+///     fn free(this: *mut Self) {
+///         assert!(LIBCOUNTER.is_held());
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// unsafe impl Movable for Counter {}
+///
+/// enum LibCounter {}
+/// static LIBCOUNTER: Serialized<LibCounter> = Serialized::new();
+///
+/// let counter = unsafe { Ptr::from_raw(Box::into_raw(Box::new(Counter(Cell::new(0))))) };
+/// let counter = Locked::new(counter, &LIBCOUNTER);
+///
+/// thread::scope(|scope| {
+///     for _ in 0..4 {
+///         scope.spawn(|| {
+///             let counter = counter.lock();
+///             counter.0.set(counter.0.get() + 1);
+///         });
+///     }
+/// });
+///
+/// assert_eq!(counter.lock().0.get(), 4);
+/// ```
+///
+/// Re-entering the library panics:
+///
+/// ```should_panic
+/// use cffi::Serialized;
+///
+/// enum LibFoo {}
+/// static LIBFOO: Serialized<LibFoo> = Serialized::new();
+///
+/// let _guard = LIBFOO.lock();
+/// let _reentered = LIBFOO.lock();
+/// ```
+pub struct Serialized<L: ?Sized> {
+    mutex: Mutex<()>,
+    owner: AtomicUsize,
+    _library: PhantomData<fn() -> L>,
+}
+
+/// Guard returned by [`Serialized::lock`].
+///
+/// The lock is released when the guard is dropped.
+pub struct SerializedGuard<'a, L: ?Sized> {
+    serialized: &'a Serialized<L>,
+    _guard: MutexGuard<'a, ()>,
+}
+
+/// Owned pointer to an object of a non-reentrant library.
+///
+/// This wraps a [`Ptr`], and only gives access to its pointee while holding
+/// the library's [`Serialized`] lock. The object is also freed while holding
+/// the lock. As all accesses are serialized, `Locked` is [`Send`] and [`Sync`]
+/// whenever `T` is [`Movable`].
+///
+/// [`Locked::lock`] takes the lock for a single object. To use several
+/// objects of the same library together, take the lock once and borrow each
+/// of them with [`Locked::get`] or [`Locked::get_mut`]:
+///
+/// ```
+/// use cffi::{Alloc, Locked, Ptr, Serialized};
+///
+/// struct Node(Option<u32>);
+///
+/// impl Alloc for Node {
+///     fn free(this: *mut Self) {
+///         drop(unsafe { Box::from_raw(this) });
+///     }
+/// }
+///
+/// enum LibNode {}
+/// static LIBNODE: Serialized<LibNode> = Serialized::new();
+///
+/// fn node(value: Option<u32>) -> Locked<Node, LibNode> {
+///     let raw = Box::into_raw(Box::new(Node(value)));
+///     Locked::new(unsafe { Ptr::from_raw(raw) }, &LIBNODE)
+/// }
+///
+/// let parent = node(None);
+/// let child = node(Some(7));
+///
+/// let mut guard = LIBNODE.lock();
+/// let value = child.get(&guard).0;
+/// parent.get_mut(&mut guard).0 = value;
+///
+/// // Handles dropped while the lock is held are freed without re-locking.
+/// drop(child);
+/// assert_eq!(parent.get(&guard).0, Some(7));
+/// ```
+pub struct Locked<T: Alloc, L: ?Sized + 'static> {
+    ptr: ManuallyDrop<Ptr<T>>,
+    serialized: &'static Serialized<L>,
+}
+
+/// Guard returned by [`Locked::lock`], giving access to the pointee.
+pub struct LockedGuard<'a, T: Alloc, L: ?Sized + 'static> {
+    locked: &'a Locked<T, L>,
+    _guard: SerializedGuard<'a, L>,
+}
+
+unsafe impl<T: Alloc + Movable, L: ?Sized> Send for Locked<T, L> {}
+unsafe impl<T: Alloc + Movable, L: ?Sized> Sync for Locked<T, L> {}
+
+impl<L: ?Sized> Serialized<L> {
+    pub const fn new() -> Serialized<L> {
+        Serialized {
+            mutex: Mutex::new(()),
+            owner: AtomicUsize::new(0),
+            _library: PhantomData,
+        }
+    }
+
+    /// Acquire the lock, blocking until it is available.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current thread already holds the lock.
+    pub fn lock(&self) -> SerializedGuard<'_, L> {
+        let thread = current_thread();
+        assert!(
+            self.owner.load(Ordering::Relaxed) != thread,
+            "re-entered a serialized library while holding its lock"
+        );
+
+        // Poisoning only means that a panic happened while the lock was held,
+        // which says nothing about the state of the C library.
+        let guard = self.mutex.lock().unwrap_or_else(PoisonError::into_inner);
+        self.owner.store(thread, Ordering::Relaxed);
+        SerializedGuard {
+            serialized: self,
+            _guard: guard,
+        }
+    }
+
+    /// Returns `true` if the current thread holds the lock.
+    pub fn is_held(&self) -> bool {
+        self.owner.load(Ordering::Relaxed) == current_thread()
+    }
+}
+
+impl<L: ?Sized> Default for Serialized<L> {
+    fn default() -> Self {
+        Serialized::new()
+    }
+}
+
+impl<L: ?Sized> fmt::Debug for Serialized<L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Serialized")
+            .field("locked", &(self.owner.load(Ordering::Relaxed) != 0))
+            .finish()
+    }
+}
+
+impl<L: ?Sized> Drop for SerializedGuard<'_, L> {
+    fn drop(&mut self) {
+        // Runs before the mutex guard is dropped.
+        self.serialized.owner.store(0, Ordering::Relaxed);
+    }
+}
+
+impl<T: Alloc, L: ?Sized> Locked<T, L> {
+    pub fn new(ptr: Ptr<T>, serialized: &'static Serialized<L>) -> Locked<T, L> {
+        Locked {
+            ptr: ManuallyDrop::new(ptr),
+            serialized,
+        }
+    }
+
+    /// Acquire the library's lock, giving access to the pointee.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current thread already holds the lock.
+    pub fn lock(&self) -> LockedGuard<'_, T, L> {
+        LockedGuard {
+            locked: self,
+            _guard: self.serialized.lock(),
+        }
+    }
+
+    /// Borrow the pointee for as long as `guard` is held.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `guard` is not a guard of this object's lock.
+    pub fn get<'g>(&'g self, guard: &'g SerializedGuard<'_, L>) -> &'g T {
+        self.check_guard(guard);
+        &self.ptr
+    }
+
+    /// Mutably borrow the pointee for as long as `guard` is held.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `guard` is not a guard of this object's lock.
+    pub fn get_mut<'g>(&'g self, guard: &'g mut SerializedGuard<'_, L>) -> &'g mut T {
+        self.check_guard(guard);
+        // The guard is borrowed uniquely, so no other reference obtained
+        // through it can be alive, and no other guard can exist.
+        unsafe { &mut *(Ptr::as_ptr(&self.ptr) as *mut T) }
+    }
+
+    fn check_guard(&self, guard: &SerializedGuard<'_, L>) {
+        assert!(
+            ptr::eq(guard.serialized, self.serialized),
+            "guard belongs to a different lock"
+        );
+    }
+
+    /// Release the pointer from the lock's protection.
+    pub fn into_inner(locked: Locked<T, L>) -> Ptr<T> {
+        let locked = ManuallyDrop::new(locked);
+        unsafe { ptr::read(&*locked.ptr) }
+    }
+
+    pub fn serialized(locked: &Locked<T, L>) -> &'static Serialized<L> {
+        locked.serialized
+    }
+}
+
+impl<T: Alloc, L: ?Sized> fmt::Debug for Locked<T, L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Locked")
+            .field(&Ptr::as_ptr(&self.ptr))
+            .finish()
+    }
+}
+
+impl<T: Alloc, L: ?Sized> Drop for Locked<T, L> {
+    fn drop(&mut self) {
+        let _guard = if self.serialized.is_held() {
+            None
+        } else {
+            Some(self.serialized.lock())
+        };
+        unsafe { ManuallyDrop::drop(&mut self.ptr) }
+    }
+}
+
+impl<T: Alloc, L: ?Sized> LockedGuard<'_, T, L> {
+    pub fn as_raw(guard: &mut LockedGuard<'_, T, L>) -> *mut T {
+        Ptr::as_ptr(&guard.locked.ptr) as *mut T
+    }
+}
+
+impl<T: Alloc, L: ?Sized> Deref for LockedGuard<'_, T, L> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.locked.ptr
+    }
+}
+
+impl<T: Alloc, L: ?Sized> DerefMut for LockedGuard<'_, T, L> {
+    fn deref_mut(&mut self) -> &mut T {
+        // The lock guarantees that this is the only guard for this object.
+        unsafe { &mut *LockedGuard::as_raw(self) }
+    }
+}
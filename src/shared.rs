@@ -0,0 +1,164 @@
+use std::{borrow::Borrow, mem, ops::Deref, ptr::NonNull};
+
+use crate::{Alloc, AtomicRetain, Movable, NullPointer, Ptr, Retain, ThreadSafe};
+
+/// Shared pointer to a reference counted object.
+///
+/// This type is to [`Rc`] what [`Ptr`] is to [`Box`]: cloning it increments
+/// the reference count through the pointee's [`Retain`] implementation, and
+/// dropping it decrements it.
+///
+/// ```
+/// use std::cell::Cell;
+/// use cffi::{Retain, Shared};
+///
+/// struct Counted(Cell<usize>);
+///
+/// impl Retain for Counted {
+///     fn retain(this: *mut Self) {
+///         let count = unsafe { &(*this).0 };
+///         count.set(count.get() + 1);
+///     }
+///
+///     fn release(this: *mut Self) {
+///         let count = unsafe { &(*this).0 };
+///         count.set(count.get() - 1);
+///         if count.get() == 0 {
+///             drop(unsafe { Box::from_raw(this) });
+///         }
+///     }
+/// }
+///
+/// let raw = Box::into_raw(Box::new(Counted(Cell::new(1))));
+/// let a = unsafe { Shared::from_raw(raw) };
+/// let b = a.clone();
+/// assert_eq!(a.0.get(), 2);
+/// drop(b);
+/// assert_eq!(a.0.get(), 1);
+/// ```
+///
+/// [`Rc`]: std::rc::Rc
+#[repr(transparent)]
+pub struct Shared<T: Retain>(NonNull<T>);
+
+/// Thread-safe shared pointer to a reference counted object.
+///
+/// Same as [`Shared`], except that it is [`Send`] and [`Sync`] when the
+/// pointee's reference count is [atomic](AtomicRetain) and the pointee itself
+/// is [`Movable`] and [`ThreadSafe`].
+#[repr(transparent)]
+pub struct AtomicShared<T: AtomicRetain>(NonNull<T>);
+
+unsafe impl<T: AtomicRetain + Movable + ThreadSafe> Send for AtomicShared<T> {}
+unsafe impl<T: AtomicRetain + Movable + ThreadSafe> Sync for AtomicShared<T> {}
+
+macro_rules! shared_impl {
+    ($name:ident, $bound:ident) => {
+        impl<T: $bound> $name<T> {
+            /// Take ownership of a reference, returning `None` if the pointer
+            /// is null.
+            ///
+            /// The reference count is not incremented.
+            ///
+            /// # Safety
+            ///
+            /// `raw` must be either null or a valid pointer to `T`, and the
+            /// caller must own one of its references.
+            pub unsafe fn new(raw: *mut T) -> Option<$name<T>> {
+                NonNull::new(raw).map($name)
+            }
+
+            /// Take ownership of a reference, failing with [`NullPointer`] if
+            /// the pointer is null.
+            ///
+            /// # Safety
+            ///
+            /// Same as for
+            #[doc = concat!("[`", stringify!($name), "::new`].")]
+            pub unsafe fn try_from_raw(raw: *mut T) -> Result<$name<T>, NullPointer> {
+                $name::new(raw).ok_or(NullPointer)
+            }
+
+            /// Take ownership of a reference.
+            ///
+            /// # Safety
+            ///
+            /// `raw` must be a valid, non-null pointer to `T`, and the caller
+            /// must own one of its references.
+            pub unsafe fn from_raw(raw: *mut T) -> $name<T> {
+                debug_assert!(
+                    !raw.is_null(),
+                    concat!(stringify!($name), "::from_raw called with a null pointer"),
+                );
+                $name(NonNull::new_unchecked(raw))
+            }
+
+            /// Acquire a new reference to an object borrowed from elsewhere.
+            ///
+            /// # Safety
+            ///
+            /// `raw` must be a valid, non-null pointer to `T`.
+            pub unsafe fn retain_raw(raw: *mut T) -> $name<T> {
+                Retain::retain(raw);
+                $name::from_raw(raw)
+            }
+
+            pub fn into_raw(ptr: $name<T>) -> *mut T {
+                let raw = ptr.0.as_ptr();
+                mem::forget(ptr);
+                raw
+            }
+
+            pub fn as_ptr(ptr: &$name<T>) -> *const T {
+                ptr.0.as_ptr()
+            }
+
+            /// Returns `true` if both pointers point to the same object.
+            pub fn ptr_eq(a: &$name<T>, b: &$name<T>) -> bool {
+                a.0 == b.0
+            }
+        }
+
+        impl<T: $bound> Clone for $name<T> {
+            fn clone(&self) -> $name<T> {
+                Retain::retain(self.0.as_ptr());
+                $name(self.0)
+            }
+        }
+
+        impl<T: $bound> AsRef<T> for $name<T> {
+            fn as_ref(&self) -> &T {
+                self
+            }
+        }
+
+        impl<T: $bound> Borrow<T> for $name<T> {
+            fn borrow(&self) -> &T {
+                self
+            }
+        }
+
+        impl<T: $bound> Deref for $name<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                unsafe { self.0.as_ref() }
+            }
+        }
+
+        impl<T: $bound> Drop for $name<T> {
+            fn drop(&mut self) {
+                Retain::release(self.0.as_ptr());
+            }
+        }
+
+        impl<T: Alloc + $bound> From<Ptr<T>> for $name<T> {
+            fn from(ptr: Ptr<T>) -> $name<T> {
+                unsafe { $name::from_raw(Ptr::into_raw(ptr)) }
+            }
+        }
+    };
+}
+
+shared_impl!(Shared, Retain);
+shared_impl!(AtomicShared, AtomicRetain);
@@ -0,0 +1,138 @@
+use std::{
+    borrow::Borrow,
+    ffi::{CStr, CString},
+    fmt,
+    ops::Deref,
+    os::raw::c_char,
+    string::FromUtf8Error,
+};
+
+use crate::{Deleter, LibcFree, NullPointer, PtrWith};
+
+/// Owned C string.
+///
+/// This is an owned `char*` returned by a C function, which dereferences to
+/// [`CStr`] (providing `to_str` and `to_string_lossy`), and is freed with
+/// a [`Deleter`] when dropped, by default libc's `free`. Libraries which
+/// provide their own function for freeing strings should use it instead.
+///
+/// ```
+/// use cffi::CStrPtr;
+///
+/// let raw = unsafe { libc::strdup(b"hello\0".as_ptr() as *const _) };
+/// let string: CStrPtr = unsafe { CStrPtr::new(raw) }.unwrap();
+/// assert_eq!(string.to_str(), Ok("hello"));
+/// assert_eq!(CStrPtr::into_string(string).unwrap(), "hello");
+///
+/// fn assert_thread_safe<T: Send + Sync>() {}
+/// assert_thread_safe::<CStrPtr>();
+/// ```
+pub struct CStrPtr<D: Deleter<c_char> = LibcFree>(PtrWith<c_char, D>);
+
+// The string is a plain sequence of bytes, which is safe to free and read on
+// any thread.
+unsafe impl<D: Deleter<c_char> + Send> Send for CStrPtr<D> {}
+unsafe impl<D: Deleter<c_char> + Sync> Sync for CStrPtr<D> {}
+
+impl<D: Deleter<c_char> + Default> CStrPtr<D> {
+    /// Take ownership of a C string, returning `None` if it is null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to a nul-terminated string
+    /// which can be freed with `D`, and must not be owned by anything else.
+    pub unsafe fn new(raw: *mut c_char) -> Option<CStrPtr<D>> {
+        CStrPtr::new_with(raw, D::default())
+    }
+
+    /// Take ownership of a C string, failing with [`NullPointer`] if it is
+    /// null.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`CStrPtr::new`].
+    pub unsafe fn try_from_raw(raw: *mut c_char) -> Result<CStrPtr<D>, NullPointer> {
+        CStrPtr::new(raw).ok_or(NullPointer)
+    }
+
+    /// Take ownership of a C string.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid, non-null pointer to a nul-terminated string
+    /// which can be freed with `D`, and must not be owned by anything else.
+    pub unsafe fn from_raw(raw: *mut c_char) -> CStrPtr<D> {
+        CStrPtr::from_raw_with(raw, D::default())
+    }
+}
+
+impl<D: Deleter<c_char>> CStrPtr<D> {
+    /// Take ownership of a C string freed with `deleter`, returning `None` if
+    /// it is null.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be either null or a valid pointer to a nul-terminated string
+    /// which can be freed with `deleter`, and must not be owned by anything
+    /// else.
+    pub unsafe fn new_with(raw: *mut c_char, deleter: D) -> Option<CStrPtr<D>> {
+        PtrWith::new(raw, deleter).map(CStrPtr)
+    }
+
+    /// Take ownership of a C string freed with `deleter`.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid, non-null pointer to a nul-terminated string
+    /// which can be freed with `deleter`, and must not be owned by anything
+    /// else.
+    pub unsafe fn from_raw_with(raw: *mut c_char, deleter: D) -> CStrPtr<D> {
+        CStrPtr(PtrWith::from_raw(raw, deleter))
+    }
+
+    pub fn into_raw(ptr: CStrPtr<D>) -> *mut c_char {
+        PtrWith::into_raw(ptr.0)
+    }
+
+    pub fn as_ptr(ptr: &CStrPtr<D>) -> *const c_char {
+        PtrWith::as_ptr(&ptr.0)
+    }
+
+    /// Copy the string into a [`String`] and free the original.
+    ///
+    /// Fails if the string is not valid UTF-8.
+    pub fn into_string(ptr: CStrPtr<D>) -> Result<String, FromUtf8Error> {
+        String::from_utf8(ptr.to_bytes().to_vec())
+    }
+
+    /// Copy the string into a [`CString`] and free the original.
+    pub fn into_c_string(ptr: CStrPtr<D>) -> CString {
+        ptr.as_ref().to_owned()
+    }
+}
+
+impl<D: Deleter<c_char>> AsRef<CStr> for CStrPtr<D> {
+    fn as_ref(&self) -> &CStr {
+        self
+    }
+}
+
+impl<D: Deleter<c_char>> Borrow<CStr> for CStrPtr<D> {
+    fn borrow(&self) -> &CStr {
+        self
+    }
+}
+
+impl<D: Deleter<c_char>> Deref for CStrPtr<D> {
+    type Target = CStr;
+
+    fn deref(&self) -> &CStr {
+        unsafe { CStr::from_ptr(PtrWith::as_ptr(&self.0)) }
+    }
+}
+
+impl<D: Deleter<c_char>> fmt::Debug for CStrPtr<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}